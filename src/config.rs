@@ -0,0 +1,318 @@
+use std::{env, fmt::Display, fs, str::FromStr};
+use thiserror::Error;
+
+const DEFAULT_FRONTEND_URL: &'static str = "http://localhost:3000";
+/// The API only serves JSON, so nothing may be loaded or framed by default
+const DEFAULT_CONTENT_SECURITY_POLICY: &'static str = "default-src 'none'; frame-ancestors 'none'";
+const DEFAULT_SPAM_NEW_ACCOUNT_HOURS: i64 = 72;
+const DEFAULT_SPAM_MAX_LISTINGS_PER_DAY: u64 = 3;
+const DEFAULT_DISPOSABLE_EMAIL_REFRESH_HOURS: u64 = 24;
+const DEFAULT_SEGMENT_REFRESH_MINUTES: u64 = 60;
+const DEFAULT_JWT_TTL_HOURS: i64 = 24 * 7;
+const DEFAULT_REQUEST_LOG_BODY_SAMPLE_RATE: f64 = 0.1;
+const DEFAULT_SLOW_QUERY_THRESHOLD_MS: u64 = 200;
+const DEFAULT_ARGON2_MEM_COST: u32 = 19 * 1024;
+const DEFAULT_ARGON2_TIME_COST: u32 = 2;
+const DEFAULT_ARGON2_LANES: u32 = 1;
+const MAX_ARGON2_LANES: u32 = 0xFF_FFFF;
+
+#[derive(Error, Debug)]
+#[error("Invalid configuration:\n  - {}", .problems.join("\n  - "))]
+pub struct ConfigError {
+    problems: Vec<String>,
+}
+
+///
+/// How clients send their JWT. Cookie mode sets an HttpOnly cookie at login and requires a CSRF
+/// token on writes; bearer mode returns the token for an `Authorization` header. With `Both`,
+/// each client picks at login with the `X-Auth-Mode` header, defaulting to cookie
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AuthMode {
+    Cookie,
+    Bearer,
+    Both,
+}
+
+impl AuthMode {
+    pub fn allows_cookie(self) -> bool {
+        self != Self::Bearer
+    }
+
+    pub fn allows_bearer(self) -> bool {
+        self != Self::Cookie
+    }
+}
+
+#[derive(Clone)]
+pub enum CaptchaProvider {
+    Disabled,
+    HCaptcha { secret: String },
+    Turnstile { secret: String },
+}
+
+///
+/// Application settings, loaded once at startup and placed in Rocket's managed state.
+///
+/// Every setting is read from an environment variable of the same name, or from the file named by
+/// `<NAME>_FILE` if that is set instead, so Docker and Kubernetes secret mounts can be used
+#[derive(Clone)]
+pub struct Config {
+    pub database_url: String,
+    /// Base URL of the web frontend, used to build links to listings
+    pub frontend_url: String,
+    pub slow_query_threshold_ms: u64,
+    pub jwt_secret: String,
+    pub public_id_secret: String,
+    pub jwt_keys_file: Option<String>,
+    pub jwt_ttl_hours: i64,
+    pub auth_mode: AuthMode,
+    /// Marks the session and CSRF cookies `Secure`. Only turn this off for local development
+    /// over plain HTTP
+    pub cookie_secure: bool,
+    pub admin_email: String,
+    pub admin_password: String,
+    pub internal_auth_enabled: bool,
+    pub internal_auth_secret: Option<String>,
+    pub request_logging: bool,
+    pub request_log_body_sample_rate: f64,
+    pub content_security_policy: String,
+    pub captcha_provider: CaptchaProvider,
+    pub spam_new_account_hours: i64,
+    pub spam_max_listings_per_day: u64,
+    pub disposable_email_source_url: Option<String>,
+    pub disposable_email_refresh_hours: u64,
+    pub disposable_email_overrides_file: Option<String>,
+    pub meetup_spots_file: Option<String>,
+    pub keyword_policy_file: Option<String>,
+    pub reserved_usernames_file: Option<String>,
+    pub collections_file: Option<String>,
+    pub announcements_file: Option<String>,
+    pub experiments_file: Option<String>,
+    pub experiment_exposure_log_file: Option<String>,
+    pub ranking_config_file: Option<String>,
+    pub segments_file: Option<String>,
+    pub segment_refresh_minutes: u64,
+    pub argon2_mem_cost: u32,
+    pub argon2_time_cost: u32,
+    pub argon2_lanes: u32,
+}
+
+/// Collects every missing or malformed setting so they can be reported together
+struct ConfigLoader {
+    problems: Vec<String>,
+}
+
+impl ConfigLoader {
+    fn optional(&mut self, name: &str) -> Option<String> {
+        let file_var = format!("{name}_FILE");
+        let value = match env::var(&file_var) {
+            Ok(path) => match fs::read_to_string(&path) {
+                Ok(contents) => Some(contents.trim_end().to_owned()),
+                Err(e) => {
+                    self.problems
+                        .push(format!("{file_var}: unable to read {path} ({e})"));
+                    None
+                }
+            },
+            Err(_) => env::var(name).ok(),
+        };
+
+        value.filter(|value| !value.is_empty())
+    }
+
+    fn required(&mut self, name: &str) -> String {
+        self.optional(name).unwrap_or_else(|| {
+            self.problems.push(format!("{name} is not set"));
+            String::new()
+        })
+    }
+
+    fn parsed<T>(&mut self, name: &str, default: T) -> T
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        match self.optional(name) {
+            Some(value) => value.parse().unwrap_or_else(|e| {
+                self.problems
+                    .push(format!("{name} has invalid value {value:?} ({e})"));
+                default
+            }),
+            None => default,
+        }
+    }
+
+    fn auth_mode(&mut self) -> AuthMode {
+        match self.optional("AUTH_MODE").as_deref() {
+            None | Some("cookie") => AuthMode::Cookie,
+            Some("bearer") => AuthMode::Bearer,
+            Some("both") => AuthMode::Both,
+            Some(mode) => {
+                self.problems.push(format!(
+                    "AUTH_MODE must be one of cookie, bearer, or both but was {mode:?}"
+                ));
+                AuthMode::Cookie
+            }
+        }
+    }
+
+    fn captcha_provider(&mut self) -> CaptchaProvider {
+        let provider = self.optional("CAPTCHA_PROVIDER");
+        let provider = match provider.as_deref() {
+            None | Some("disabled") => return CaptchaProvider::Disabled,
+            Some(provider) => provider.to_owned(),
+        };
+
+        let secret = self.required("CAPTCHA_SECRET");
+        match provider.as_str() {
+            "hcaptcha" => CaptchaProvider::HCaptcha { secret },
+            "turnstile" => CaptchaProvider::Turnstile { secret },
+            _ => {
+                self.problems.push(format!(
+                    "CAPTCHA_PROVIDER must be one of hcaptcha, turnstile, or disabled but was {provider:?}"
+                ));
+                CaptchaProvider::Disabled
+            }
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let mut loader = ConfigLoader {
+            problems: Vec::new(),
+        };
+
+        let jwt_secret = loader.required("SECRET");
+        let public_id_secret = loader.required("PUBLIC_ID_SECRET");
+        if !public_id_secret.is_empty() && public_id_secret == jwt_secret {
+            loader
+                .problems
+                .push("PUBLIC_ID_SECRET must be different from SECRET".to_owned());
+        }
+        let internal_auth_enabled = loader.parsed("INTERNAL_AUTH_ENABLED", false);
+        // Without a secret only mTLS callers could get in, which locks out the admin API unless
+        // client certificates are set up, so a secret is required
+        let internal_auth_secret = match internal_auth_enabled {
+            true => Some(loader.required("INTERNAL_AUTH_SECRET")),
+            false => loader.optional("INTERNAL_AUTH_SECRET"),
+        };
+        let config = Self {
+            database_url: loader.required("DATABASE_URL"),
+            frontend_url: loader
+                .optional("FRONTEND_URL")
+                .unwrap_or_else(|| DEFAULT_FRONTEND_URL.to_owned())
+                .trim_end_matches('/')
+                .to_owned(),
+            slow_query_threshold_ms: loader
+                .parsed("SLOW_QUERY_THRESHOLD_MS", DEFAULT_SLOW_QUERY_THRESHOLD_MS),
+            public_id_secret,
+            jwt_secret,
+            jwt_keys_file: loader.optional("JWT_KEYS_FILE"),
+            jwt_ttl_hours: loader.parsed("JWT_TTL_HOURS", DEFAULT_JWT_TTL_HOURS),
+            auth_mode: loader.auth_mode(),
+            cookie_secure: loader.parsed("COOKIE_SECURE", true),
+            admin_email: loader.required("ADMIN_EMAIL"),
+            admin_password: loader.required("ADMIN_PASSWORD"),
+            internal_auth_enabled,
+            internal_auth_secret,
+            request_logging: loader.parsed("REQUEST_LOGGING", false),
+            request_log_body_sample_rate: loader.parsed(
+                "REQUEST_LOG_BODY_SAMPLE_RATE",
+                DEFAULT_REQUEST_LOG_BODY_SAMPLE_RATE,
+            ),
+            content_security_policy: loader
+                .optional("CONTENT_SECURITY_POLICY")
+                .unwrap_or_else(|| DEFAULT_CONTENT_SECURITY_POLICY.to_owned()),
+            captcha_provider: loader.captcha_provider(),
+            spam_new_account_hours: loader
+                .parsed("SPAM_NEW_ACCOUNT_HOURS", DEFAULT_SPAM_NEW_ACCOUNT_HOURS),
+            spam_max_listings_per_day: loader.parsed(
+                "SPAM_MAX_LISTINGS_PER_DAY",
+                DEFAULT_SPAM_MAX_LISTINGS_PER_DAY,
+            ),
+            disposable_email_source_url: loader.optional("DISPOSABLE_EMAIL_SOURCE_URL"),
+            disposable_email_refresh_hours: loader.parsed(
+                "DISPOSABLE_EMAIL_REFRESH_HOURS",
+                DEFAULT_DISPOSABLE_EMAIL_REFRESH_HOURS,
+            ),
+            disposable_email_overrides_file: loader.optional("DISPOSABLE_EMAIL_OVERRIDES_FILE"),
+            meetup_spots_file: loader.optional("MEETUP_SPOTS_FILE"),
+            keyword_policy_file: loader.optional("KEYWORD_POLICY_FILE"),
+            reserved_usernames_file: loader.optional("RESERVED_USERNAMES_FILE"),
+            collections_file: loader.optional("COLLECTIONS_FILE"),
+            announcements_file: loader.optional("ANNOUNCEMENTS_FILE"),
+            experiments_file: loader.optional("EXPERIMENTS_FILE"),
+            experiment_exposure_log_file: loader.optional("EXPERIMENT_EXPOSURE_LOG_FILE"),
+            ranking_config_file: loader.optional("RANKING_CONFIG_FILE"),
+            segments_file: loader.optional("SEGMENTS_FILE"),
+            segment_refresh_minutes: loader
+                .parsed("SEGMENT_REFRESH_MINUTES", DEFAULT_SEGMENT_REFRESH_MINUTES),
+            argon2_mem_cost: loader.parsed("ARGON2_MEM_COST", DEFAULT_ARGON2_MEM_COST),
+            argon2_time_cost: loader.parsed("ARGON2_TIME_COST", DEFAULT_ARGON2_TIME_COST),
+            argon2_lanes: loader.parsed("ARGON2_LANES", DEFAULT_ARGON2_LANES),
+        };
+
+        // Limits enforced by rust-argon2, checked here so a bad value fails at startup instead of
+        // on the first login or registration
+        if config.argon2_time_cost < 1 {
+            loader
+                .problems
+                .push("ARGON2_TIME_COST must be at least 1".to_owned());
+        }
+        if !(1..=MAX_ARGON2_LANES).contains(&config.argon2_lanes) {
+            loader.problems.push(format!(
+                "ARGON2_LANES must be between 1 and {MAX_ARGON2_LANES} but was {}",
+                config.argon2_lanes
+            ));
+        } else if config.argon2_mem_cost < 8 * config.argon2_lanes {
+            loader.problems.push(format!(
+                "ARGON2_MEM_COST must be at least 8 KiB per lane ({} KiB) but was {}",
+                8 * config.argon2_lanes,
+                config.argon2_mem_cost
+            ));
+        }
+        if !(0.0..=1.0).contains(&config.request_log_body_sample_rate) {
+            loader.problems.push(format!(
+                "REQUEST_LOG_BODY_SAMPLE_RATE must be between 0 and 1 but was {}",
+                config.request_log_body_sample_rate
+            ));
+        }
+
+        if !loader.problems.is_empty() {
+            return Err(ConfigError {
+                problems: loader.problems,
+            });
+        }
+
+        Ok(config)
+    }
+
+    ///
+    /// Reads just `PUBLIC_ID_SECRET`, for public ids encoded before the full config is loaded
+    pub fn public_id_secret_from_env() -> Option<String> {
+        ConfigLoader {
+            problems: Vec::new(),
+        }
+        .optional("PUBLIC_ID_SECRET")
+    }
+}
+
+#[cfg(test)]
+impl Config {
+    ///
+    /// Config with only the required settings filled in, for tests that never reach a database
+    pub fn for_tests() -> Self {
+        for (name, value) in [
+            ("DATABASE_URL", "postgres://localhost/tekxchange-test"),
+            ("SECRET", "test-secret"),
+            ("PUBLIC_ID_SECRET", "test-public-id-secret"),
+            ("ADMIN_EMAIL", "admin@example.com"),
+            ("ADMIN_PASSWORD", "password"),
+        ] {
+            env::set_var(name, value);
+        }
+
+        Self::from_env().unwrap()
+    }
+}
@@ -0,0 +1,732 @@
+use rand::{distributions::Alphanumeric, Rng};
+use rocket::{
+    data::{Data, ToByteUnit},
+    http::ContentType,
+    response::{status::Created, stream::TextStream},
+    serde::json::Json,
+    tokio::{fs, io::BufReader},
+    Route, State,
+};
+use serde_json::json;
+use std::{collections::HashMap, env, path::Path, sync::Arc};
+
+use crate::{
+    config::Config,
+    db::slow_query_count,
+    fairings::{MaintenanceGroup, MaintenanceMode, MaintenanceWindow},
+    models::{
+        announcement::{AnnouncementDetails, AnnouncementReturn},
+        bulk::{BulkIds, BulkItemResult},
+        collection::CollectionDetails,
+        dto,
+        experiment::Experiment,
+        internal_caller::InternalCaller,
+        keyword_policy::{KeywordCheck, KeywordRule},
+        meetup::MeetupSpot,
+        public_id::PublicId,
+        ranking::{RankingWeights, ScoreExplanation},
+        segment::{SegmentPredicate, SegmentReturn},
+        user::AdminUser,
+    },
+    services::{
+        AnnouncementBoard, AnnouncementBoardError, BackupService, BackupServiceError, BackupTable,
+        Collections, CollectionsError, DisposableEmailBlocklist, DisposableEmailError,
+        ExperimentService, ExperimentServiceError, JwtKeyring, JwtKeyringError, KeywordPolicy,
+        KeywordPolicyError, MeetupSpots, MeetupSpotsError, ProductService, ProductServiceError,
+        RankingConfig, RankingConfigError, ReservedUsernames, ReservedUsernamesError, Segments,
+        SegmentsError, UserService, UserServiceError,
+    },
+};
+
+/// Largest backup body accepted by an import
+const IMPORT_LIMIT_MIB: u64 = 1024;
+
+dto! {
+    struct EmailDomainDto {
+        domain: String,
+    }
+}
+
+#[get("/email-domains", format = "json")]
+async fn get_blocked_email_domains(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    blocklist: &State<Arc<DisposableEmailBlocklist>>,
+) -> Json<Vec<String>> {
+    Json(blocklist.added_domains())
+}
+
+#[post("/email-domains", format = "json", data = "<domain>")]
+async fn block_email_domain(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    blocklist: &State<Arc<DisposableEmailBlocklist>>,
+    domain: Json<EmailDomainDto>,
+) -> Result<Created<()>, DisposableEmailError> {
+    blocklist.add_domain(&domain.0.domain)?;
+    println!(
+        "{} blocked email domain {}",
+        admin.user.username, domain.0.domain
+    );
+
+    Ok(Created::new(""))
+}
+
+#[delete("/email-domains/<domain>")]
+async fn unblock_email_domain(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    blocklist: &State<Arc<DisposableEmailBlocklist>>,
+    domain: &str,
+) -> Result<(), DisposableEmailError> {
+    blocklist.remove_domain(domain)?;
+    println!("{} unblocked email domain {domain}", admin.user.username);
+
+    Ok(())
+}
+
+dto! {
+    struct RotatedKeyDto {
+        kid: String,
+    }
+}
+
+#[post("/jwt/rotate")]
+async fn rotate_jwt_key(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    jwt_keyring: &State<Arc<JwtKeyring>>,
+) -> Result<Json<RotatedKeyDto>, JwtKeyringError> {
+    let kid = jwt_keyring.rotate()?;
+    println!(
+        "{} rotated the JWT signing key to {kid}",
+        admin.user.username
+    );
+
+    Ok(Json(RotatedKeyDto { kid }))
+}
+
+dto! {
+    struct MetricsDto {
+        slow_queries: u64,
+    }
+}
+
+#[get("/metrics", format = "json")]
+async fn get_metrics(_internal: InternalCaller, _admin: AdminUser) -> Json<MetricsDto> {
+    Json(MetricsDto {
+        slow_queries: slow_query_count(),
+    })
+}
+
+///
+/// Every product as newline-delimited JSON. Rows are streamed from the database as the response
+/// is written, so a failure partway through truncates the export and is only visible in the logs
+#[get("/products/export")]
+async fn export_products(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    product_service: ProductService,
+) -> (ContentType, TextStream![String]) {
+    println!("{} exported all products", admin.user.username);
+
+    let stream = TextStream! {
+        let products = match product_service.stream_products().await {
+            Ok(products) => products,
+            Err(e) => {
+                println!("Product export failed: {e}");
+                return;
+            }
+        };
+
+        for await product in products {
+            let product = match product {
+                Ok(product) => product,
+                Err(e) => {
+                    println!("Product export failed: {e}");
+                    return;
+                }
+            };
+            yield json!(product).to_string() + "\n";
+        }
+    };
+
+    (ContentType::new("application", "x-ndjson"), stream)
+}
+
+#[post("/products/bulk-delete", format = "json", data = "<products>")]
+async fn bulk_delete_products(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    mut product_service: ProductService,
+    products: Json<BulkIds>,
+) -> Result<Json<Vec<BulkItemResult>>, ProductServiceError> {
+    let ids: Vec<i64> = products.0.ids.iter().map(|id| id.0).collect();
+    let results = product_service.bulk_delete_products(&ids).await?;
+    for result in results.iter().filter(|result| result.ok) {
+        println!("{} deleted product {}", admin.user.username, result.id);
+    }
+
+    Ok(Json(results))
+}
+
+///
+/// Raw rows of `table` as newline-delimited JSON, streamed from the database
+#[get("/backup/<table>")]
+async fn export_table(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    backup_service: BackupService,
+    table: BackupTable,
+) -> (ContentType, TextStream![String]) {
+    println!("{} exported the {table:?} table", admin.user.username);
+
+    let stream = TextStream! {
+        let rows = match backup_service.stream_table(table).await {
+            Ok(rows) => rows,
+            Err(e) => {
+                println!("{table:?} export failed: {e}");
+                return;
+            }
+        };
+
+        for await row in rows {
+            let row = match row {
+                Ok(row) => row,
+                Err(e) => {
+                    println!("{table:?} export failed: {e}");
+                    return;
+                }
+            };
+            yield row.to_string() + "\n";
+        }
+    };
+
+    (ContentType::new("application", "x-ndjson"), stream)
+}
+
+dto! {
+    struct ImportedDto {
+        imported: u64,
+    }
+}
+
+///
+/// Restores rows produced by `export_table`. The import runs in one transaction, so any bad row
+/// leaves the table untouched
+#[post("/backup/<table>", data = "<rows>")]
+async fn import_table(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    backup_service: BackupService,
+    table: BackupTable,
+    rows: Data<'_>,
+) -> Result<Json<ImportedDto>, BackupServiceError> {
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect();
+    let upload_path = env::temp_dir().join(format!("tekxchange-import-{suffix}.ndjson"));
+    let imported = import_upload(&backup_service, table, rows, &upload_path).await;
+    fs::remove_file(&upload_path).await.ok();
+    let imported = imported?;
+    println!(
+        "{} imported {imported} rows into the {table:?} table",
+        admin.user.username
+    );
+
+    Ok(Json(ImportedDto { imported }))
+}
+
+///
+/// Saves the body to `path` before importing it, so a body over `IMPORT_LIMIT_MIB` is rejected
+/// without touching the table instead of being restored partially
+async fn import_upload(
+    backup_service: &BackupService,
+    table: BackupTable,
+    rows: Data<'_>,
+    path: &Path,
+) -> Result<u64, BackupServiceError> {
+    let upload = rows
+        .open(IMPORT_LIMIT_MIB.mebibytes())
+        .into_file(path)
+        .await
+        .map_err(BackupServiceError::ReadError)?;
+    if !upload.is_complete() {
+        return Err(BackupServiceError::TooLarge(IMPORT_LIMIT_MIB));
+    }
+    drop(upload);
+
+    let rows = fs::File::open(path)
+        .await
+        .map_err(BackupServiceError::ReadError)?;
+    backup_service
+        .import_table(table, BufReader::new(rows))
+        .await
+}
+
+#[get("/maintenance", format = "json")]
+async fn get_maintenance(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    maintenance: &State<MaintenanceMode>,
+) -> Json<HashMap<&'static str, MaintenanceWindow>> {
+    Json(maintenance.active())
+}
+
+#[put("/maintenance/<group>", format = "json", data = "<window>")]
+async fn enable_maintenance(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    maintenance: &State<MaintenanceMode>,
+    group: MaintenanceGroup,
+    window: Json<MaintenanceWindow>,
+) {
+    println!(
+        "{} enabled maintenance for {group:?} ({:?})",
+        admin.user.username, window.0
+    );
+    maintenance.enable(group, window.0);
+}
+
+#[delete("/maintenance/<group>")]
+async fn disable_maintenance(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    maintenance: &State<MaintenanceMode>,
+    group: MaintenanceGroup,
+) {
+    maintenance.disable(group);
+    println!("{} disabled maintenance for {group:?}", admin.user.username);
+}
+
+#[get("/announcements", format = "json")]
+async fn get_announcements(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    board: &State<AnnouncementBoard>,
+) -> Json<Vec<AnnouncementReturn>> {
+    Json(board.all())
+}
+
+#[post("/announcements", format = "json", data = "<announcement>")]
+async fn create_announcement(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    board: &State<AnnouncementBoard>,
+    announcement: Json<AnnouncementDetails>,
+) -> Result<Created<Json<AnnouncementReturn>>, AnnouncementBoardError> {
+    let id = board.create(announcement.0.clone())?;
+    println!("{} created announcement {id}", admin.user.username);
+
+    Ok(Created::new("").body(Json(AnnouncementReturn {
+        id,
+        details: announcement.0,
+    })))
+}
+
+#[put("/announcements/<id>", format = "json", data = "<announcement>")]
+async fn update_announcement(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    board: &State<AnnouncementBoard>,
+    id: u64,
+    announcement: Json<AnnouncementDetails>,
+) -> Result<Option<()>, AnnouncementBoardError> {
+    if !board.update(id, announcement.0)? {
+        return Ok(None);
+    }
+    println!("{} updated announcement {id}", admin.user.username);
+
+    Ok(Some(()))
+}
+
+#[delete("/announcements/<id>")]
+async fn delete_announcement(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    board: &State<AnnouncementBoard>,
+    id: u64,
+) -> Result<Option<()>, AnnouncementBoardError> {
+    if !board.remove(id)? {
+        return Ok(None);
+    }
+    println!("{} deleted announcement {id}", admin.user.username);
+
+    Ok(Some(()))
+}
+
+#[get("/meetup-spots", format = "json")]
+async fn get_meetup_spots(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    meetup_spots: &State<MeetupSpots>,
+) -> Json<Vec<MeetupSpot>> {
+    Json(meetup_spots.all())
+}
+
+///
+/// Replaces the whole meetup spot dataset
+#[put("/meetup-spots", format = "json", data = "<spots>")]
+async fn import_meetup_spots(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    meetup_spots: &State<MeetupSpots>,
+    spots: Json<Vec<MeetupSpot>>,
+) -> Result<Json<ImportedDto>, MeetupSpotsError> {
+    let imported = meetup_spots.import(spots.0)? as u64;
+    println!("{} imported {imported} meetup spots", admin.user.username);
+
+    Ok(Json(ImportedDto { imported }))
+}
+
+#[get("/keyword-policy", format = "json")]
+async fn get_keyword_policy(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    keyword_policy: &State<KeywordPolicy>,
+) -> Json<Vec<KeywordRule>> {
+    Json(keyword_policy.rules())
+}
+
+///
+/// Replaces every keyword rule
+#[put("/keyword-policy", format = "json", data = "<rules>")]
+async fn set_keyword_policy(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    keyword_policy: &State<KeywordPolicy>,
+    rules: Json<Vec<KeywordRule>>,
+) -> Result<(), KeywordPolicyError> {
+    let count = rules.0.len();
+    keyword_policy.set_rules(rules.0)?;
+    println!("{} set {count} keyword policy rules", admin.user.username);
+
+    Ok(())
+}
+
+dto! {
+    struct KeywordTestDto {
+        text: String,
+    }
+}
+
+///
+/// How the keyword policy would treat a title or username, without storing anything
+#[post("/keyword-policy/test", format = "json", data = "<test>")]
+async fn test_keyword_policy(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    keyword_policy: &State<KeywordPolicy>,
+    test: Json<KeywordTestDto>,
+) -> Json<KeywordCheck> {
+    Json(keyword_policy.check(&test.0.text))
+}
+
+#[get("/reserved-usernames", format = "json")]
+async fn get_reserved_usernames(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    reserved_usernames: &State<ReservedUsernames>,
+) -> Json<Vec<String>> {
+    Json(reserved_usernames.list())
+}
+
+dto! {
+    struct ReservedUsernameDto {
+        username: String,
+    }
+}
+
+#[post("/reserved-usernames", format = "json", data = "<username>")]
+async fn reserve_username(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    reserved_usernames: &State<ReservedUsernames>,
+    username: Json<ReservedUsernameDto>,
+) -> Result<Created<()>, ReservedUsernamesError> {
+    reserved_usernames.add(&username.0.username)?;
+    println!(
+        "{} reserved username {}",
+        admin.user.username, username.0.username
+    );
+
+    Ok(Created::new(""))
+}
+
+#[delete("/reserved-usernames/<username>")]
+async fn release_username(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    reserved_usernames: &State<ReservedUsernames>,
+    username: &str,
+) -> Result<(), ReservedUsernamesError> {
+    reserved_usernames.remove(username)?;
+    println!("{} released username {username}", admin.user.username);
+
+    Ok(())
+}
+
+dto! {
+    struct MergeUsersDto {
+        primary_id: PublicId,
+        duplicate_id: PublicId,
+    }
+}
+
+dto! {
+    struct MergedUsersDto {
+        moved_products: Vec<PublicId>,
+    }
+}
+
+///
+/// Moves a duplicate account's listings to the primary account. The moved listing ids are
+/// returned and logged so the merge can be reversed
+#[post("/users/merge", format = "json", data = "<merge>")]
+async fn merge_users(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    mut user_service: UserService,
+    merge: Json<MergeUsersDto>,
+) -> Result<Json<MergedUsersDto>, UserServiceError> {
+    let MergeUsersDto {
+        primary_id,
+        duplicate_id,
+    } = merge.0;
+    let moved = user_service
+        .merge_users(primary_id.0, duplicate_id.0)
+        .await?;
+    let moved_products: Vec<PublicId> = moved.into_iter().map(PublicId::from).collect();
+    println!(
+        "{} merged user {duplicate_id} into {primary_id}, moving products {}",
+        admin.user.username,
+        moved_products
+            .iter()
+            .map(PublicId::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(Json(MergedUsersDto { moved_products }))
+}
+
+#[put("/collections/<slug>", format = "json", data = "<collection>")]
+async fn put_collection(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    collections: &State<Collections>,
+    slug: &str,
+    collection: Json<CollectionDetails>,
+) -> Result<(), CollectionsError> {
+    collections.upsert(slug, collection.0)?;
+    println!("{} saved collection {slug}", admin.user.username);
+
+    Ok(())
+}
+
+#[delete("/collections/<slug>")]
+async fn delete_collection(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    collections: &State<Collections>,
+    slug: &str,
+) -> Result<Option<()>, CollectionsError> {
+    if !collections.remove(slug)? {
+        return Ok(None);
+    }
+    println!("{} deleted collection {slug}", admin.user.username);
+
+    Ok(Some(()))
+}
+
+#[get("/experiments", format = "json")]
+async fn get_experiments(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    experiments: &State<ExperimentService>,
+) -> Json<Vec<Experiment>> {
+    Json(experiments.all())
+}
+
+///
+/// Replaces all experiment definitions
+#[put("/experiments", format = "json", data = "<definitions>")]
+async fn put_experiments(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    experiments: &State<ExperimentService>,
+    definitions: Json<Vec<Experiment>>,
+) -> Result<Json<ImportedDto>, ExperimentServiceError> {
+    let imported = experiments.replace(definitions.0)? as u64;
+    println!("{} imported {imported} experiments", admin.user.username);
+
+    Ok(Json(ImportedDto { imported }))
+}
+
+#[get("/ranking", format = "json")]
+async fn get_ranking_weights(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    ranking_config: &State<RankingConfig>,
+) -> Json<RankingWeights> {
+    Json(ranking_config.weights())
+}
+
+#[put("/ranking", format = "json", data = "<weights>")]
+async fn put_ranking_weights(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    ranking_config: &State<RankingConfig>,
+    weights: Json<RankingWeights>,
+) -> Result<(), RankingConfigError> {
+    ranking_config.update(weights.0)?;
+    println!("{} updated search ranking weights", admin.user.username);
+
+    Ok(())
+}
+
+///
+/// Re-reads the ranking weights from `RANKING_CONFIG_FILE`
+#[post("/ranking/reload")]
+async fn reload_ranking_weights(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    ranking_config: &State<RankingConfig>,
+) -> Result<Json<RankingWeights>, RankingConfigError> {
+    let weights = ranking_config.reload()?;
+    println!("{} reloaded search ranking weights", admin.user.username);
+
+    Ok(Json(weights))
+}
+
+///
+/// Explains a listing's search score for a query under the current weights
+#[get("/ranking/explain/<id>?<q>", format = "json")]
+async fn explain_ranking(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    mut product_service: ProductService,
+    ranking_config: &State<RankingConfig>,
+    q: &str,
+    id: PublicId,
+) -> Result<Json<ScoreExplanation>, ProductServiceError> {
+    let explanation = product_service
+        .explain_search_score(id.0, q, ranking_config.weights())
+        .await?;
+
+    Ok(Json(explanation))
+}
+
+#[get("/segments", format = "json")]
+async fn get_segments(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    segments: &State<Arc<Segments>>,
+) -> Json<Vec<SegmentReturn>> {
+    Json(segments.all())
+}
+
+#[put("/segments/<name>", format = "json", data = "<predicate>")]
+async fn put_segment(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    segments: &State<Arc<Segments>>,
+    name: &str,
+    predicate: Json<SegmentPredicate>,
+) -> Result<(), SegmentsError> {
+    segments.upsert(name, predicate.0)?;
+    println!("{} saved segment {name}", admin.user.username);
+
+    Ok(())
+}
+
+#[delete("/segments/<name>")]
+async fn delete_segment(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    segments: &State<Arc<Segments>>,
+    name: &str,
+) -> Result<Option<()>, SegmentsError> {
+    if !segments.remove(name)? {
+        return Ok(None);
+    }
+    println!("{} deleted segment {name}", admin.user.username);
+
+    Ok(Some(()))
+}
+
+///
+/// Members of a segment as of its last materialization
+#[get("/segments/<name>/members", format = "json")]
+async fn get_segment_members(
+    _internal: InternalCaller,
+    _admin: AdminUser,
+    segments: &State<Arc<Segments>>,
+    name: &str,
+) -> Option<Json<Vec<PublicId>>> {
+    let members = segments.members(name)?;
+
+    Some(Json(members.into_iter().map(PublicId).collect()))
+}
+
+///
+/// Recomputes every segment's members now instead of waiting for the refresh job
+#[post("/segments/refresh")]
+async fn refresh_segments(
+    _internal: InternalCaller,
+    admin: AdminUser,
+    config: &State<Config>,
+    segments: &State<Arc<Segments>>,
+) -> Result<Json<Vec<SegmentReturn>>, SegmentsError> {
+    segments.refresh(config).await?;
+    println!("{} refreshed user segments", admin.user.username);
+
+    Ok(Json(segments.all()))
+}
+
+pub const BASE_PATH: &str = "/api/admin";
+
+pub fn routes() -> Vec<Route> {
+    routes![
+        get_blocked_email_domains,
+        block_email_domain,
+        unblock_email_domain,
+        rotate_jwt_key,
+        get_metrics,
+        export_products,
+        bulk_delete_products,
+        export_table,
+        import_table,
+        get_maintenance,
+        enable_maintenance,
+        disable_maintenance,
+        get_announcements,
+        create_announcement,
+        update_announcement,
+        delete_announcement,
+        get_meetup_spots,
+        import_meetup_spots,
+        get_keyword_policy,
+        set_keyword_policy,
+        test_keyword_policy,
+        get_reserved_usernames,
+        reserve_username,
+        release_username,
+        merge_users,
+        put_collection,
+        delete_collection,
+        get_experiments,
+        put_experiments,
+        get_ranking_weights,
+        put_ranking_weights,
+        reload_ranking_weights,
+        explain_ranking,
+        get_segments,
+        put_segment,
+        delete_segment,
+        get_segment_members,
+        refresh_segments
+    ]
+}
@@ -0,0 +1,20 @@
+use rocket::{serde::json::Json, Route, State};
+
+use crate::{
+    models::{announcement::AnnouncementReturn, user::AuthUser},
+    services::AnnouncementBoard,
+};
+
+#[get("/active", format = "json")]
+async fn get_active_announcements(
+    user: Option<AuthUser>,
+    board: &State<AnnouncementBoard>,
+) -> Json<Vec<AnnouncementReturn>> {
+    Json(board.active(user.is_some()))
+}
+
+pub const BASE_PATH: &str = "/api/announcements";
+
+pub fn routes() -> Vec<Route> {
+    routes![get_active_announcements]
+}
@@ -0,0 +1,41 @@
+use rocket::{serde::json::Json, Route, State};
+
+use crate::{
+    models::{collection::CollectionReturn, product::ProductReturn},
+    services::{Collections, ProductService, ProductServiceError},
+};
+
+const PRODUCTS_DEFAULT_LIMIT: u64 = 20;
+const PRODUCTS_MAX_LIMIT: u64 = 100;
+
+#[get("/", format = "json")]
+async fn get_collections(collections: &State<Collections>) -> Json<Vec<CollectionReturn>> {
+    Json(collections.all())
+}
+
+#[get("/<slug>/products?<limit>", format = "json")]
+async fn get_collection_products(
+    mut product_service: ProductService,
+    collections: &State<Collections>,
+    slug: &str,
+    limit: Option<u64>,
+) -> Result<Option<Json<Vec<ProductReturn>>>, ProductServiceError> {
+    let collection = match collections.get(slug) {
+        Some(collection) => collection,
+        None => return Ok(None),
+    };
+    let limit = limit
+        .unwrap_or(PRODUCTS_DEFAULT_LIMIT)
+        .min(PRODUCTS_MAX_LIMIT);
+    let products = product_service
+        .get_collection_products(&collection, limit)
+        .await?;
+
+    Ok(Some(Json(products)))
+}
+
+pub const BASE_PATH: &str = "/api/collections";
+
+pub fn routes() -> Vec<Route> {
+    routes![get_collections, get_collection_products]
+}
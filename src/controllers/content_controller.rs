@@ -0,0 +1,31 @@
+use rocket::{serde::json::Json, Route};
+
+use crate::{models::dto, services::render_markdown};
+
+dto! {
+    struct MarkdownDto {
+        markdown: String,
+    }
+}
+
+dto! {
+    struct RenderedDto {
+        html: String,
+    }
+}
+
+///
+/// Sanitized HTML for a piece of markdown, so the frontend can preview what a description will
+/// look like without rendering untrusted content itself
+#[post("/markdown", format = "json", data = "<content>")]
+async fn render(content: Json<MarkdownDto>) -> Json<RenderedDto> {
+    Json(RenderedDto {
+        html: render_markdown(&content.0.markdown),
+    })
+}
+
+pub const BASE_PATH: &str = "/api/content";
+
+pub fn routes() -> Vec<Route> {
+    routes![render]
+}
@@ -0,0 +1,38 @@
+use rocket::{serde::json::Json, Route, State};
+use std::sync::Arc;
+
+use crate::{
+    models::{experiment::ExperimentAssignment, user::AuthUser},
+    services::{ExperimentService, Segments},
+};
+
+#[get("/assignments", format = "json")]
+async fn get_assignments(
+    user: AuthUser,
+    experiments: &State<ExperimentService>,
+    segments: &State<Arc<Segments>>,
+) -> Json<Vec<ExperimentAssignment>> {
+    let user_id = user.user.id;
+    Json(experiments.assignments(user_id, |segment| segments.contains(segment, user_id)))
+}
+
+///
+/// Called by the frontend when a user actually sees an experiment's variant
+#[post("/<key>/exposure")]
+async fn record_exposure(
+    user: AuthUser,
+    experiments: &State<ExperimentService>,
+    segments: &State<Arc<Segments>>,
+    key: &str,
+) -> Option<Json<ExperimentAssignment>> {
+    let user_id = user.user.id;
+    experiments
+        .record_exposure(user_id, key, |segment| segments.contains(segment, user_id))
+        .map(Json)
+}
+
+pub const BASE_PATH: &str = "/api/experiments";
+
+pub fn routes() -> Vec<Route> {
+    routes![get_assignments, record_exposure]
+}
@@ -0,0 +1,34 @@
+use rocket::{serde::json::Json, Route, State};
+
+use crate::{
+    models::meetup::MeetupSuggestion,
+    services::{MeetupSpots, MeetupSpotsError},
+};
+
+const SUGGESTIONS_DEFAULT_RADIUS_KM: f64 = 10.0;
+const SUGGESTIONS_MAX_RADIUS_KM: f64 = 100.0;
+const SUGGESTIONS_LIMIT: usize = 10;
+
+// Query parameters are named as the frontend sends them
+#[allow(non_snake_case)]
+#[get("/suggestions?<lat>&<lng>&<radiusKm>", format = "json")]
+async fn get_suggestions(
+    meetup_spots: &State<MeetupSpots>,
+    lat: f64,
+    lng: f64,
+    radiusKm: Option<f64>,
+) -> Result<Json<Vec<MeetupSuggestion>>, MeetupSpotsError> {
+    let radius_km = radiusKm
+        .filter(|radius_km| radius_km.is_finite())
+        .unwrap_or(SUGGESTIONS_DEFAULT_RADIUS_KM)
+        .clamp(0.0, SUGGESTIONS_MAX_RADIUS_KM);
+
+    let suggestions = meetup_spots.nearby(lat, lng, radius_km, SUGGESTIONS_LIMIT)?;
+    Ok(Json(suggestions))
+}
+
+pub const BASE_PATH: &str = "/api/meetups";
+
+pub fn routes() -> Vec<Route> {
+    routes![get_suggestions]
+}
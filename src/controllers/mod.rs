@@ -1,8 +1,88 @@
 use rocket::{Build, Rocket};
-mod product_controller;
-mod user_controller;
 
-pub fn mount_routes(r: Rocket<Build>) -> Rocket<Build> {
-    r.mount("/api/users", user_controller::routes())
-        .mount("/api/products", product_controller::routes())
+///
+/// Declares each controller module and mounts its `routes()` at its `BASE_PATH`. A controller
+/// cannot be added without also being mounted, since both come from the same entry
+macro_rules! controllers {
+    ($($controller:ident),* $(,)?) => {
+        $(mod $controller;)*
+
+        pub fn mount_routes(r: Rocket<Build>) -> Rocket<Build> {
+            r$(.mount($controller::BASE_PATH, $controller::routes()))*
+        }
+    };
+}
+
+controllers![
+    admin_controller,
+    announcement_controller,
+    collection_controller,
+    content_controller,
+    experiment_controller,
+    meetup_controller,
+    product_controller,
+    user_controller,
+];
+
+#[cfg(test)]
+mod tests {
+    use rocket::local::blocking::Client;
+    use std::{fs, path::Path, sync::Arc};
+
+    use crate::{
+        build_rocket,
+        config::Config,
+        services::{DisposableEmailBlocklist, JwtKeyring, Segments},
+    };
+
+    const ROUTE_ATTRIBUTES: &[&str] = &["#[get(", "#[post(", "#[put(", "#[patch(", "#[delete("];
+
+    ///
+    /// Checks the built rocket against the controller files on disk rather than the
+    /// `controllers!` list, so a controller file left out of that list fails the test
+    #[test]
+    fn every_controller_is_mounted() {
+        let config = Config::for_tests();
+        let rocket = build_rocket(
+            config.clone(),
+            Arc::new(JwtKeyring::from_config(&config).unwrap()),
+            Arc::new(DisposableEmailBlocklist::from_config(&config).unwrap()),
+            Arc::new(Segments::from_config(&config).unwrap()),
+        );
+        let client = Client::untracked(rocket).expect("rocket failed to ignite");
+
+        let controllers_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/controllers");
+        let mut controller_count = 0;
+        for entry in fs::read_dir(controllers_dir).unwrap() {
+            let path = entry.unwrap().path();
+            let file_name = path.file_name().unwrap().to_string_lossy().into_owned();
+            if !file_name.ends_with("_controller.rs") {
+                continue;
+            }
+            controller_count += 1;
+
+            let source = fs::read_to_string(&path).unwrap();
+            let base_path = source
+                .lines()
+                .find_map(|line| line.strip_prefix("pub const BASE_PATH: &str = \""))
+                .and_then(|rest| rest.strip_suffix("\";"))
+                .unwrap_or_else(|| panic!("{file_name} does not declare a BASE_PATH"));
+            let declared = source
+                .lines()
+                .filter(|line| ROUTE_ATTRIBUTES.iter().any(|attr| line.starts_with(attr)))
+                .count();
+            let mounted = client
+                .rocket()
+                .routes()
+                .filter(|route| route.uri.base() == base_path)
+                .count();
+
+            assert!(declared > 0, "{file_name} declares no routes");
+            assert_eq!(
+                mounted, declared,
+                "{file_name} declares {declared} routes but {mounted} are mounted at {base_path}"
+            );
+        }
+        assert!(controller_count > 0, "no controller files found");
+    }
 }
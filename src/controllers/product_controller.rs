@@ -1,25 +1,40 @@
 use rocket::{
     response::status::{Accepted, Created},
     serde::json::Json,
-    Route,
+    Route, State,
 };
 
 use crate::{
+    config::Config,
     models::{
-        product::{ProductDetails, ProductReturn},
-        user::AuthUser,
+        bulk::BulkIds,
+        patch::Patch,
+        product::{ProductComparison, ProductDetails, ProductPatch, ProductQuality, ProductReturn},
+        public_id::PublicId,
+        scope::{ProductsRead, ProductsWrite, Scoped},
+    },
+    services::{
+        KeywordPolicy, ProductService, ProductServiceError, QrCodeError, QrFormat, QrImage,
+        RankingConfig,
     },
-    services::{ProductService, ProductServiceError},
 };
 
+const SEARCH_DEFAULT_LIMIT: u64 = 20;
+const SEARCH_MAX_LIMIT: u64 = 100;
+const MAX_COMPARED_PRODUCTS: usize = 4;
+
 #[post("/create", format = "json", data = "<product_create>")]
 async fn create_product(
     mut product_service: ProductService,
-    product_create: Json<ProductDetails>,
-    auth_user: AuthUser,
+    keyword_policy: &State<KeywordPolicy>,
+    mut product_create: Json<ProductDetails>,
+    user: Scoped<ProductsWrite>,
 ) -> Result<Created<()>, ProductServiceError> {
+    product_create.0.title = keyword_policy
+        .apply("title", product_create.0.title)
+        .map_err(ProductServiceError::PolicyError)?;
     product_service
-        .create_new_product(product_create.0, auth_user)
+        .create_new_product(product_create.0, user.auth_user)
         .await?;
 
     Ok(Created::new(""))
@@ -28,22 +43,90 @@ async fn create_product(
 #[get("/product/<id>", format = "json")]
 async fn get_product_by_id(
     mut product_service: ProductService,
-    id: i64,
+    id: PublicId,
 ) -> Result<Json<ProductReturn>, ProductServiceError> {
-    let found_product = product_service.get_product_by_id(id).await?;
+    let found_product = product_service.get_product_by_id(id.0).await?;
 
     Ok(Json(found_product))
 }
 
+#[get("/product/<id>/quality", format = "json")]
+async fn get_product_quality(
+    mut product_service: ProductService,
+    id: PublicId,
+    user: Scoped<ProductsRead>,
+) -> Result<Json<ProductQuality>, ProductServiceError> {
+    let quality = product_service
+        .get_product_quality(id.0, user.auth_user)
+        .await?;
+
+    Ok(Json(quality))
+}
+
+///
+/// QR code linking to the listing on the frontend, for printing on labels
+#[get("/product/<id>/qr?<format>")]
+async fn get_product_qr_code(
+    mut product_service: ProductService,
+    config: &State<Config>,
+    id: PublicId,
+    format: Option<QrFormat>,
+) -> Result<Result<QrImage, QrCodeError>, ProductServiceError> {
+    product_service.get_product_by_id(id.0).await?;
+    let url = format!("{}/products/{id}", config.frontend_url);
+
+    Ok(QrImage::render(&url, format.unwrap_or_default()))
+}
+
+#[get("/search?<q>&<limit>", format = "json")]
+async fn search_products(
+    mut product_service: ProductService,
+    ranking_config: &State<RankingConfig>,
+    q: &str,
+    limit: Option<u64>,
+) -> Result<Json<Vec<ProductReturn>>, ProductServiceError> {
+    let limit = limit.unwrap_or(SEARCH_DEFAULT_LIMIT).min(SEARCH_MAX_LIMIT);
+    let found_products = product_service
+        .search_products(q, limit, ranking_config.weights())
+        .await?;
+
+    Ok(Json(found_products))
+}
+
 #[put("/product/<id>", format = "json", data = "<product>")]
 async fn update_product_by_id(
     mut product_service: ProductService,
-    id: i64,
-    user: AuthUser,
-    product: Json<ProductDetails>,
+    id: PublicId,
+    user: Scoped<ProductsWrite>,
+    keyword_policy: &State<KeywordPolicy>,
+    mut product: Json<ProductDetails>,
 ) -> Result<Accepted<()>, ProductServiceError> {
+    product.0.title = keyword_policy
+        .apply("title", product.0.title)
+        .map_err(ProductServiceError::PolicyError)?;
     product_service
-        .update_product_by_id(id, product.0, user)
+        .update_product_by_id(id.0, product.0, user.auth_user)
+        .await?;
+    Ok(Accepted(None))
+}
+
+#[patch("/product/<id>", format = "json", data = "<patch>")]
+async fn patch_product_by_id(
+    mut product_service: ProductService,
+    id: PublicId,
+    user: Scoped<ProductsWrite>,
+    keyword_policy: &State<KeywordPolicy>,
+    mut patch: Json<ProductPatch>,
+) -> Result<Accepted<()>, ProductServiceError> {
+    if let Patch::Value(title) = patch.0.title {
+        patch.0.title = Patch::Value(
+            keyword_policy
+                .apply("title", title)
+                .map_err(ProductServiceError::PolicyError)?,
+        );
+    }
+    product_service
+        .patch_product_by_id(id.0, patch.0, user.auth_user)
         .await?;
     Ok(Accepted(None))
 }
@@ -51,19 +134,41 @@ async fn update_product_by_id(
 #[delete("/product/<id>")]
 async fn delete_product_by_id(
     mut product_service: ProductService,
-    id: i64,
-    user: AuthUser,
+    id: PublicId,
+    user: Scoped<ProductsWrite>,
 ) -> Result<(), ProductServiceError> {
-    product_service.delete_product_by_id(id, user).await?;
+    product_service
+        .delete_product_by_id(id.0, user.auth_user)
+        .await?;
 
     Ok(())
 }
 
+#[post("/compare", format = "json", data = "<products>")]
+async fn compare_products(
+    mut product_service: ProductService,
+    products: Json<BulkIds>,
+) -> Result<Json<ProductComparison>, ProductServiceError> {
+    let ids: Vec<i64> = products.0.ids.iter().map(|id| id.0).collect();
+    let comparison = product_service
+        .compare_products(&ids, MAX_COMPARED_PRODUCTS)
+        .await?;
+
+    Ok(Json(comparison))
+}
+
+pub const BASE_PATH: &str = "/api/products";
+
 pub fn routes() -> Vec<Route> {
     routes![
         create_product,
         get_product_by_id,
+        get_product_quality,
+        get_product_qr_code,
+        search_products,
+        compare_products,
         update_product_by_id,
+        patch_product_by_id,
         delete_product_by_id
     ]
 }
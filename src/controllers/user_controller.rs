@@ -1,19 +1,51 @@
 use crate::{
-    models::user::{AuthUser, UserLogin, UserRegister, UserReturnDto},
-    services::{UserService, UserServiceError},
+    config::{AuthMode, Config},
+    fairings::CSRF_COOKIE,
+    models::{
+        dto,
+        scope::{Scoped, UsersRead},
+        user::{ClientAuthMode, UserLogin, UserRegister, UserReturnDto, TOKEN_COOKIE},
+    },
+    services::{
+        CaptchaService, DisposableEmailBlocklist, KeywordPolicy, ReservedUsernames, UserService,
+        UserServiceError,
+    },
 };
+use rand::{distributions::Alphanumeric, Rng};
 use rocket::{
     http::{Cookie, CookieJar, SameSite},
     response::status::Created,
     serde::json::Json,
-    Route,
+    Either, Route, State,
 };
+use std::sync::Arc;
 
 #[post("/register", format = "json", data = "<user_register>")]
 async fn register(
     mut user_service: UserService,
-    user_register: Json<UserRegister>,
+    captcha_service: CaptchaService<'_>,
+    email_blocklist: &State<Arc<DisposableEmailBlocklist>>,
+    keyword_policy: &State<KeywordPolicy>,
+    reserved_usernames: &State<ReservedUsernames>,
+    mut user_register: Json<UserRegister>,
 ) -> Result<Created<()>, UserServiceError> {
+    captcha_service
+        .verify()
+        .await
+        .map_err(UserServiceError::CaptchaError)?;
+
+    if email_blocklist.is_blocked(&user_register.0.email) {
+        return Err(UserServiceError::DisposableEmail);
+    }
+    user_register.0.username = keyword_policy
+        .apply("username", user_register.0.username)
+        .map_err(UserServiceError::PolicyError)?;
+
+    let staff_usernames = user_service.staff_usernames().await?;
+    if reserved_usernames.is_reserved(&user_register.0.username, &staff_usernames) {
+        return Err(UserServiceError::ReservedUsername);
+    }
+
     user_service
         .create_user(user_register.0)
         .await?;
@@ -23,9 +55,10 @@ async fn register(
     Ok(created_response)
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct UsernameExistsDto {
-    username: String,
+dto! {
+    struct UsernameExistsDto {
+        username: String,
+    }
 }
 
 #[post("/username_exists", format = "json", data = "<username>")]
@@ -40,9 +73,10 @@ async fn username_exists(
     Ok(Json(found))
 }
 
-#[derive(serde::Serialize, serde::Deserialize, Debug)]
-struct EmailExistsDto {
-    email: String,
+dto! {
+    struct EmailExistsDto {
+        email: String,
+    }
 }
 
 #[post("/email_exists", format = "json", data = "<email>")]
@@ -57,36 +91,78 @@ async fn email_exists(
     Ok(Json(found))
 }
 
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+dto! {
+    struct TokenDto {
+        token: String,
+    }
+}
+
 #[post("/login", format = "json", data = "<login>")]
 async fn login(
     mut user_service: UserService,
+    captcha_service: CaptchaService<'_>,
     login: Json<UserLogin>,
+    auth_mode: ClientAuthMode,
+    config: &State<Config>,
     cookies: &CookieJar<'_>,
-) -> Result<(), UserServiceError> {
-    let token = user_service.login(login.0).await?;
+) -> Result<Either<(), Json<TokenDto>>, UserServiceError> {
+    let user = user_service.find_login_user(&login.0).await?;
+
+    captcha_service
+        .verify_login(user.id)
+        .await
+        .map_err(UserServiceError::CaptchaError)?;
+
+    let token = match user_service.login(&user, &login.0.password).await {
+        Err(UserServiceError::InvalidPassword) => {
+            captcha_service.record_failed_login(user.id);
+            return Err(UserServiceError::InvalidPassword);
+        }
+        token => token?,
+    };
+    captcha_service.clear_failed_logins(user.id);
+
+    if auth_mode.0 == AuthMode::Bearer {
+        return Ok(Either::Right(Json(TokenDto { token })));
+    }
 
-    let token_cookie = Cookie::build("token", token)
+    let token_cookie = Cookie::build(TOKEN_COOKIE, token)
         .same_site(SameSite::Lax)
+        .http_only(true)
+        .secure(config.cookie_secure)
+        .finish();
+    let csrf_token: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(CSRF_TOKEN_LENGTH)
+        .map(char::from)
+        .collect();
+    let csrf_cookie = Cookie::build(CSRF_COOKIE, csrf_token)
+        .same_site(SameSite::Lax)
+        .secure(config.cookie_secure)
         .finish();
 
     cookies.add(token_cookie);
+    cookies.add(csrf_cookie);
 
-    Ok(())
+    Ok(Either::Left(()))
 }
 
 #[get("/logout")]
 async fn logout(cookies: &CookieJar<'_>) {
-    let token_cookie = cookies.get_pending("token");
-
-    if let Some(cookie) = token_cookie {
-        cookies.remove(cookie);
+    for name in [TOKEN_COOKIE, CSRF_COOKIE] {
+        if let Some(cookie) = cookies.get_pending(name) {
+            cookies.remove(cookie);
+        }
     }
 }
 
 #[get("/user/info")]
-async fn get_user_info(auth_user: AuthUser) -> Json<UserReturnDto> {
+async fn get_user_info(user: Scoped<UsersRead>) -> Json<UserReturnDto> {
+    let auth_user = user.auth_user;
     let to_return = UserReturnDto {
-        id: auth_user.user.id,
+        id: auth_user.user.id.into(),
         email: auth_user.user.email,
         username: auth_user.user.username,
         role: auth_user.user.role,
@@ -95,6 +171,8 @@ async fn get_user_info(auth_user: AuthUser) -> Json<UserReturnDto> {
     Json(to_return)
 }
 
+pub const BASE_PATH: &str = "/api/users";
+
 pub fn routes() -> Vec<Route> {
     return routes![
         register,
@@ -1,14 +1,26 @@
 use rocket::{response::Responder, Response, http::Status};
-use sea_orm::{Database, DatabaseConnection, DbErr};
-use std::env;
+use sea_orm::{
+    metric, sea_query::Value, ConnectionTrait, Database, DatabaseConnection, DbBackend, DbErr,
+    EntityName, Statement,
+};
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
 use thiserror::Error;
 
+use crate::config::Config;
+
+static SLOW_QUERY_COUNT: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Error, Debug)]
 pub enum DbError {
     #[error("Unable to connect to database")]
     ConnectionError(DbErr),
-    #[error("Environment variables are not set correctly")]
-    EnvironmentError,
+    #[error("Unable to set up pg_trgm product search (is the pg_trgm extension available?): {0}")]
+    SearchSetupFailed(DbErr),
+    #[error("Application config is not available")]
+    ConfigError,
 }
 
 impl<'r> Responder<'r, 'static> for DbError {
@@ -19,11 +31,84 @@ impl<'r> Responder<'r, 'static> for DbError {
     }
 }
 
-pub async fn establish_connection() -> Result<DatabaseConnection, DbError> {
-    let db_url = env::var("DATABASE_URL").or_else(|_| Err(DbError::EnvironmentError))?;
+///
+/// Number of queries that exceeded `SLOW_QUERY_THRESHOLD_MS` since startup
+pub fn slow_query_count() -> u64 {
+    SLOW_QUERY_COUNT.load(Ordering::Relaxed)
+}
+
+fn redact_value(value: &Value) -> String {
+    match value {
+        Value::Bool(_)
+        | Value::TinyInt(_)
+        | Value::SmallInt(_)
+        | Value::Int(_)
+        | Value::BigInt(_)
+        | Value::TinyUnsigned(_)
+        | Value::SmallUnsigned(_)
+        | Value::Unsigned(_)
+        | Value::BigUnsigned(_)
+        | Value::Float(_)
+        | Value::Double(_) => format!("{value:?}"),
+        _ => "[REDACTED]".to_owned(),
+    }
+}
+
+fn log_slow_query(info: &metric::Info<'_>) {
+    SLOW_QUERY_COUNT.fetch_add(1, Ordering::Relaxed);
+
+    let params = info
+        .statement
+        .values
+        .as_ref()
+        .map(|values| values.0.iter().map(redact_value).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    println!(
+        "Slow query ({}ms{}): {} {:?}",
+        info.elapsed.as_millis(),
+        if info.failed { ", failed" } else { "" },
+        info.statement.sql,
+        params
+    );
+}
 
-    let res = Database::connect(db_url)
+pub async fn establish_connection(config: &Config) -> Result<DatabaseConnection, DbError> {
+    let mut res = Database::connect(&config.database_url)
         .await
         .map_err(|e| DbError::ConnectionError(e))?;
+
+    if config.slow_query_threshold_ms > 0 {
+        let threshold = Duration::from_millis(config.slow_query_threshold_ms);
+        res.set_metric_callback(move |info| {
+            if info.elapsed >= threshold {
+                log_slow_query(info);
+            }
+        });
+    }
+
     Ok(res)
 }
+
+///
+/// Product search uses pg_trgm's `word_similarity`. Creates the extension and a trigram index on
+/// product titles if they don't exist yet, so search fails at startup rather than on every request
+pub async fn ensure_search_support(db_connection: &DatabaseConnection) -> Result<(), DbError> {
+    let product_table = entity::product::Entity.table_name();
+    let statements = [
+        "CREATE EXTENSION IF NOT EXISTS pg_trgm".to_owned(),
+        format!(
+            "CREATE INDEX IF NOT EXISTS {product_table}_title_trgm_idx \
+            ON \"{product_table}\" USING gin (product_title gin_trgm_ops)"
+        ),
+    ];
+
+    for sql in statements {
+        db_connection
+            .execute(Statement::from_string(DbBackend::Postgres, sql))
+            .await
+            .map_err(DbError::SearchSetupFailed)?;
+    }
+
+    Ok(())
+}
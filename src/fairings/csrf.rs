@@ -0,0 +1,95 @@
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{uri::Origin, Method, Status},
+    response::Responder,
+    Build, Data, Request, Response, Rocket,
+};
+use serde_json::json;
+
+use crate::{
+    config::Config,
+    models::{internal_caller::constant_time_eq, user::TOKEN_COOKIE},
+};
+
+/// Cookie holding the CSRF token, readable by the frontend so it can echo it in `CSRF_HEADER`
+pub const CSRF_COOKIE: &'static str = "csrf_token";
+const CSRF_HEADER: &'static str = "X-CSRF-Token";
+/// Requests failing the CSRF check are rerouted here
+const REJECTED_PATH: &'static str = "/csrf-rejected";
+/// Routes that don't act on the signed in session. Login issues a fresh `csrf_token`, so a client
+/// left with a token cookie but no CSRF cookie can recover by logging in again
+const EXEMPT_PATHS: [&'static str; 2] = ["/api/users/login", "/api/users/register"];
+
+struct Rejected;
+
+impl<'r> Responder<'r, 'static> for Rejected {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        Response::build_from(
+            json!({ "error": format!("Missing or invalid {CSRF_HEADER} header") })
+                .respond_to(request)?,
+        )
+        .status(Status::Forbidden)
+        .ok()
+    }
+}
+
+#[get("/csrf-rejected")]
+fn rejected() -> Rejected {
+    Rejected
+}
+
+///
+/// Double-submit CSRF protection for cookie auth. Writes authenticated by the token cookie must
+/// repeat the `csrf_token` cookie in the `X-CSRF-Token` header. Bearer-authenticated and anonymous
+/// requests are not checked, since the browser doesn't attach their credentials on its own
+pub struct CsrfGuard;
+
+#[rocket::async_trait]
+impl Fairing for CsrfGuard {
+    fn info(&self) -> Info {
+        Info {
+            name: "CSRF guard",
+            kind: Kind::Ignite | Kind::Request,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![rejected]))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let auth_mode = match req.rocket().state::<Config>() {
+            Some(config) => config.auth_mode,
+            None => return,
+        };
+        if !auth_mode.allows_cookie()
+            || matches!(req.method(), Method::Get | Method::Head | Method::Options)
+            || EXEMPT_PATHS.contains(&req.uri().path().as_str())
+        {
+            return;
+        }
+
+        let uses_bearer = auth_mode.allows_bearer()
+            && req
+                .headers()
+                .get_one("Authorization")
+                .is_some_and(|header| header.starts_with("Bearer "));
+        if uses_bearer || req.cookies().get(TOKEN_COOKIE).is_none() {
+            return;
+        }
+
+        let expected = req.cookies().get(CSRF_COOKIE).map(|cookie| cookie.value());
+        let provided = req.headers().get_one(CSRF_HEADER);
+        let valid = match (expected, provided) {
+            (Some(expected), Some(provided)) => {
+                constant_time_eq(expected.as_bytes(), provided.as_bytes())
+            }
+            _ => false,
+        };
+
+        if !valid {
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(REJECTED_PATH).unwrap());
+        }
+    }
+}
@@ -0,0 +1,175 @@
+use rocket::{
+    fairing::{self, Fairing, Info, Kind},
+    http::{uri::Origin, Header, Method, Status},
+    request::FromParam,
+    response::Responder,
+    Build, Data, Request, Response, Rocket,
+};
+use serde_json::json;
+use std::{collections::HashMap, sync::RwLock};
+
+use crate::models::dto;
+
+/// Requests blocked by maintenance are rerouted here
+const UNAVAILABLE_PATH: &'static str = "/maintenance-unavailable";
+/// `/api/<prefix>` that is never blocked, so maintenance can always be turned off
+const ADMIN_PREFIX: &'static str = "admin";
+
+///
+/// Route groups maintenance can be enabled for, named after their `/api/<group>` prefix. `All`
+/// covers every `/api` route. Admin routes are never blocked so maintenance can always be turned
+/// off
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum MaintenanceGroup {
+    All,
+    Users,
+    Products,
+}
+
+impl MaintenanceGroup {
+    fn name(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Users => "users",
+            Self::Products => "products",
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for MaintenanceGroup {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        match param {
+            "all" => Ok(Self::All),
+            "users" => Ok(Self::Users),
+            "products" => Ok(Self::Products),
+            _ => Err(param),
+        }
+    }
+}
+
+dto! {
+    #[derive(Clone)]
+    pub struct MaintenanceWindow {
+        /// Only block requests other than GET, HEAD, and OPTIONS, leaving the API readable
+        pub writes_only: bool,
+        pub retry_after_seconds: u64,
+    }
+}
+
+///
+/// Route groups currently under maintenance, toggled through the admin API
+#[derive(Default)]
+pub struct MaintenanceMode {
+    windows: RwLock<HashMap<MaintenanceGroup, MaintenanceWindow>>,
+}
+
+impl MaintenanceMode {
+    pub fn enable(&self, group: MaintenanceGroup, window: MaintenanceWindow) {
+        self.windows.write().unwrap().insert(group, window);
+    }
+
+    pub fn disable(&self, group: MaintenanceGroup) {
+        self.windows.write().unwrap().remove(&group);
+    }
+
+    pub fn active(&self) -> HashMap<&'static str, MaintenanceWindow> {
+        self.windows
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(group, window)| (group.name(), window.clone()))
+            .collect()
+    }
+
+    ///
+    /// The window blocking a request to `group`, or to an `/api` route outside any named group
+    /// when `group` is None
+    fn blocking(
+        &self,
+        group: Option<MaintenanceGroup>,
+        method: Method,
+    ) -> Option<MaintenanceWindow> {
+        let is_read = matches!(method, Method::Get | Method::Head | Method::Options);
+        let windows = self.windows.read().unwrap();
+        group
+            .into_iter()
+            .chain([MaintenanceGroup::All])
+            .filter_map(|group| windows.get(&group))
+            .find(|window| !window.writes_only || !is_read)
+            .cloned()
+    }
+}
+
+struct BlockedBy(Option<MaintenanceWindow>);
+
+struct Unavailable;
+
+impl<'r> Responder<'r, 'static> for Unavailable {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let retry_after = request
+            .local_cache(|| BlockedBy(None))
+            .0
+            .as_ref()
+            .map(|window| window.retry_after_seconds)
+            .unwrap_or_default();
+
+        Response::build_from(
+            json!({ "error": "This part of the API is down for maintenance" })
+                .respond_to(request)?,
+        )
+        .status(Status::ServiceUnavailable)
+        .header(Header::new("Retry-After", retry_after.to_string()))
+        .ok()
+    }
+}
+
+#[get("/maintenance-unavailable")]
+fn unavailable() -> Unavailable {
+    Unavailable
+}
+
+///
+/// Answers requests to route groups under maintenance with a 503 before they reach a handler.
+/// Expects a `MaintenanceMode` in managed state
+pub struct MaintenanceGate;
+
+#[rocket::async_trait]
+impl Fairing for MaintenanceGate {
+    fn info(&self) -> Info {
+        Info {
+            name: "Maintenance gate",
+            kind: Kind::Ignite | Kind::Request,
+        }
+    }
+
+    async fn on_ignite(&self, rocket: Rocket<Build>) -> fairing::Result {
+        Ok(rocket.mount("/", routes![unavailable]))
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, _: &mut Data<'_>) {
+        let maintenance = match req.rocket().state::<MaintenanceMode>() {
+            Some(maintenance) => maintenance,
+            None => return,
+        };
+
+        let mut segments = req.uri().path().segments();
+        let window = match (segments.next(), segments.next()) {
+            (Some("api"), Some(ADMIN_PREFIX)) => None,
+            (Some("api"), prefix) => {
+                let group = prefix
+                    .and_then(|prefix| MaintenanceGroup::from_param(prefix).ok())
+                    .filter(|group| *group != MaintenanceGroup::All);
+                maintenance.blocking(group, req.method())
+            }
+            _ => None,
+        };
+
+        if let Some(window) = window {
+            req.local_cache(|| BlockedBy(Some(window)));
+            req.set_method(Method::Get);
+            req.set_uri(Origin::parse(UNAVAILABLE_PATH).unwrap());
+        }
+    }
+}
@@ -0,0 +1,9 @@
+mod csrf;
+mod maintenance;
+mod request_logger;
+mod security_headers;
+
+pub use csrf::{CsrfGuard, CSRF_COOKIE};
+pub use maintenance::{MaintenanceGate, MaintenanceGroup, MaintenanceMode, MaintenanceWindow};
+pub use request_logger::RequestLogger;
+pub use security_headers::SecurityHeaders;
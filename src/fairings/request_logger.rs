@@ -0,0 +1,167 @@
+use rand::Rng;
+use rocket::{
+    config::LogLevel,
+    fairing::{Fairing, Info, Kind},
+    http::Method,
+    Data, Request, Response,
+};
+use serde_json::Value;
+use std::time::Instant;
+
+const BODY_SAMPLE_BYTES: usize = 512;
+const REDACTED: &'static str = "[REDACTED]";
+/// Fields whose names contain any of these are always redacted
+const SENSITIVE_KEYS: &[&'static str] = &[
+    "password",
+    "token",
+    "secret",
+    "email",
+    "authorization",
+    "captcha",
+];
+
+///
+/// Captured when the request arrives, so the log shows what the client asked for even when a
+/// later fairing rewrites the request
+struct RequestLogState {
+    started: Instant,
+    method: Method,
+    target: String,
+    body_sample: Option<String>,
+}
+
+///
+/// Logs the method, path, status, and latency of every request while Rocket's log level is
+/// `normal` or `debug`. At `debug`, a sample of JSON request bodies is logged as well. Passwords,
+/// tokens, secrets, and email addresses are redacted from query strings and bodies
+pub struct RequestLogger {
+    body_sample_rate: f64,
+}
+
+impl RequestLogger {
+    pub fn new(body_sample_rate: f64) -> Self {
+        Self {
+            body_sample_rate: body_sample_rate.clamp(0.0, 1.0),
+        }
+    }
+}
+
+fn is_sensitive_key(key: &str) -> bool {
+    let key = key.to_lowercase();
+    SENSITIVE_KEYS
+        .iter()
+        .any(|sensitive| key.contains(sensitive))
+}
+
+fn looks_like_email(value: &str) -> bool {
+    match value.split_once('@') {
+        Some((user, domain)) => !user.is_empty() && domain.contains('.'),
+        None => false,
+    }
+}
+
+fn redact_json(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if is_sensitive_key(key) {
+                    *value = Value::String(REDACTED.to_owned());
+                } else {
+                    redact_json(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_json),
+        Value::String(string) if looks_like_email(string) => *string = REDACTED.to_owned(),
+        _ => {}
+    }
+}
+
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) if is_sensitive_key(key) || looks_like_email(value) => {
+                format!("{key}={REDACTED}")
+            }
+            _ => pair.to_owned(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+///
+/// Redacted JSON body or a size hint; bodies that don't fit in the peek buffer can't be parsed
+/// and are never logged verbatim
+fn redact_body(body: &[u8], complete: bool) -> String {
+    if complete {
+        if let Ok(mut json) = serde_json::from_slice::<Value>(body) {
+            redact_json(&mut json);
+            return json.to_string();
+        }
+    }
+
+    format!("<{} bytes not logged>", body.len())
+}
+
+#[rocket::async_trait]
+impl Fairing for RequestLogger {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request logger",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    async fn on_request(&self, req: &mut Request<'_>, data: &mut Data<'_>) {
+        let log_level = req.rocket().config().log_level;
+        let sample_body =
+            log_level == LogLevel::Debug && rand::thread_rng().gen_bool(self.body_sample_rate);
+
+        let body_sample = if sample_body {
+            let body = data.peek(BODY_SAMPLE_BYTES).await.to_vec();
+            Some(redact_body(&body, data.peek_complete()))
+        } else {
+            None
+        };
+
+        let query = req
+            .uri()
+            .query()
+            .map(|query| format!("?{}", redact_query(query.as_str())))
+            .unwrap_or_default();
+        let target = format!("{}{}", req.uri().path(), query);
+
+        req.local_cache(|| RequestLogState {
+            started: Instant::now(),
+            method: req.method(),
+            target,
+            body_sample,
+        });
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut Response<'r>) {
+        let log_level = req.rocket().config().log_level;
+        if log_level == LogLevel::Critical || log_level == LogLevel::Off {
+            return;
+        }
+
+        let state = req.local_cache(|| RequestLogState {
+            started: Instant::now(),
+            method: req.method(),
+            target: req.uri().path().to_string(),
+            body_sample: None,
+        });
+
+        println!(
+            "{} {} -> {} in {}ms",
+            state.method,
+            state.target,
+            res.status().code,
+            state.started.elapsed().as_millis()
+        );
+        if let Some(ref body) = state.body_sample {
+            println!("  body: {body}");
+        }
+    }
+}
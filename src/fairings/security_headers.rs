@@ -0,0 +1,45 @@
+use rocket::{
+    fairing::{Fairing, Info, Kind},
+    http::Header,
+    Request, Response,
+};
+
+const HSTS: &'static str = "max-age=31536000; includeSubDomains";
+
+///
+/// Sets strict security headers on every response. A route that needs a looser policy, such as
+/// one serving HTML or media, can set its own `Content-Security-Policy` and it will be kept
+pub struct SecurityHeaders {
+    content_security_policy: String,
+}
+
+impl SecurityHeaders {
+    pub fn new(content_security_policy: &str) -> Self {
+        Self {
+            content_security_policy: content_security_policy.to_owned(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl Fairing for SecurityHeaders {
+    fn info(&self) -> Info {
+        Info {
+            name: "Security headers",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, _: &'r Request<'_>, res: &mut Response<'r>) {
+        res.set_header(Header::new("Strict-Transport-Security", HSTS));
+        res.set_header(Header::new("X-Content-Type-Options", "nosniff"));
+        res.set_header(Header::new("Referrer-Policy", "no-referrer"));
+        res.set_header(Header::new("X-Frame-Options", "DENY"));
+        if !res.headers().contains("Content-Security-Policy") {
+            res.set_header(Header::new(
+                "Content-Security-Policy",
+                self.content_security_policy.clone(),
+            ));
+        }
+    }
+}
@@ -1,22 +1,42 @@
 #[macro_use]
 extern crate rocket;
+mod config;
 mod controllers;
 mod db;
+mod fairings;
 mod models;
 mod services;
+use config::Config;
+use fairings::{CsrfGuard, MaintenanceGate, MaintenanceMode, RequestLogger, SecurityHeaders};
 use migration::{Migrator, MigratorTrait};
-use services::UserService;
-use std::env;
+use rocket::{Build, Rocket};
+use services::{
+    captcha_verifier_from_config, AnnouncementBoard, Collections, DisposableEmailBlocklist,
+    ExperimentService, FailedLogins, JwtKeyring, KeywordPolicy, MeetupSpots, RankingConfig,
+    ReservedUsernames, Segments, UserService,
+};
+use std::{process, sync::Arc};
 
 use crate::models::user::UserRegister;
 
 #[launch]
 pub async fn rocket() -> _ {
     dotenvy::dotenv().ok();
-    let conn = db::establish_connection().await.unwrap();
+    let config = Config::from_env().unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
+
+    let conn = db::establish_connection(&config).await.unwrap();
     Migrator::up(&conn, None).await.unwrap();
+    db::ensure_search_support(&conn).await.unwrap_or_else(|e| {
+        eprintln!("{e}");
+        process::exit(1);
+    });
 
-    let mut user_service = UserService::new(conn);
+    models::public_id::init(&config);
+    let jwt_keyring = Arc::new(JwtKeyring::from_config(&config).unwrap());
+    let mut user_service = UserService::new(conn, &config, jwt_keyring.clone());
     let found_admin = user_service
         .username_exists(crate::models::user::ADMIN_USERNAME)
         .await
@@ -24,11 +44,9 @@ pub async fn rocket() -> _ {
 
     if !found_admin {
         println!("No admin found -- Seeding new admin");
-        let admin_email = env::var("ADMIN_EMAIL").unwrap();
-        let admin_password = env::var("ADMIN_PASSWORD").unwrap();
         let user_register = UserRegister {
-            email: admin_email,
-            password: admin_password,
+            email: config.admin_email.clone(),
+            password: config.admin_password.clone(),
             username: crate::models::user::ADMIN_USERNAME.to_owned(),
         };
         let user_id = user_service.create_user(user_register).await.unwrap();
@@ -38,5 +56,57 @@ pub async fn rocket() -> _ {
             .unwrap();
     }
 
-    controllers::mount_routes(rocket::build())
+    let email_blocklist = Arc::new(DisposableEmailBlocklist::from_config(&config).unwrap());
+    email_blocklist.clone().spawn_refresh_job();
+    let segments = Arc::new(Segments::from_config(&config).unwrap());
+    segments.clone().spawn_refresh_job(config.clone());
+
+    build_rocket(config, jwt_keyring, email_blocklist, segments)
+}
+
+///
+/// Attaches the fairings, managed state, and routes. Background jobs are started by the caller
+fn build_rocket(
+    config: Config,
+    jwt_keyring: Arc<JwtKeyring>,
+    email_blocklist: Arc<DisposableEmailBlocklist>,
+    segments: Arc<Segments>,
+) -> Rocket<Build> {
+    let captcha_verifier = captcha_verifier_from_config(&config);
+    let meetup_spots = MeetupSpots::from_config(&config).unwrap();
+    let keyword_policy = KeywordPolicy::from_config(&config).unwrap();
+    let reserved_usernames = ReservedUsernames::from_config(&config).unwrap();
+    let collections = Collections::from_config(&config).unwrap();
+    let announcements = AnnouncementBoard::from_config(&config).unwrap();
+    let experiments = ExperimentService::from_config(&config).unwrap();
+    let ranking_config = RankingConfig::from_config(&config).unwrap();
+
+    // The logger records each request as it arrives, so it goes before the gates that rewrite
+    // blocked requests
+    let mut rocket = rocket::build();
+    if config.request_logging {
+        rocket = rocket.attach(RequestLogger::new(config.request_log_body_sample_rate));
+    }
+    rocket = rocket
+        .attach(SecurityHeaders::new(&config.content_security_policy))
+        .attach(MaintenanceGate)
+        .attach(CsrfGuard);
+
+    controllers::mount_routes(
+        rocket
+            .manage(config)
+            .manage(jwt_keyring)
+            .manage(captcha_verifier)
+            .manage(email_blocklist)
+            .manage(FailedLogins::default())
+            .manage(MaintenanceMode::default())
+            .manage(announcements)
+            .manage(meetup_spots)
+            .manage(keyword_policy)
+            .manage(reserved_usernames)
+            .manage(collections)
+            .manage(experiments)
+            .manage(ranking_config)
+            .manage(segments),
+    )
 }
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+///
+/// Who an announcement is shown to
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum Audience {
+    Everyone,
+    SignedIn,
+    SignedOut,
+}
+
+impl Audience {
+    pub fn includes(self, signed_in: bool) -> bool {
+        match self {
+            Self::Everyone => true,
+            Self::SignedIn => signed_in,
+            Self::SignedOut => !signed_in,
+        }
+    }
+}
+
+dto! {
+    #[derive(Clone)]
+    pub struct AnnouncementDetails {
+        pub message: String,
+        pub severity: Severity,
+        pub starts_at: DateTime<Utc>,
+        /// Shown until removed when not set
+        pub ends_at: Option<DateTime<Utc>>,
+        pub audience: Audience,
+    }
+}
+
+dto! {
+    pub struct AnnouncementReturn {
+        pub id: u64,
+        #[serde(flatten)]
+        pub details: AnnouncementDetails,
+    }
+}
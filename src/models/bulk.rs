@@ -0,0 +1,17 @@
+use super::public_id::PublicId;
+
+dto! {
+    pub struct BulkIds {
+        pub ids: Vec<PublicId>,
+    }
+}
+
+dto! {
+    ///
+    /// Outcome of one item of a bulk operation. `error` is set when `ok` is false
+    pub struct BulkItemResult {
+        pub id: PublicId,
+        pub ok: bool,
+        pub error: Option<String>,
+    }
+}
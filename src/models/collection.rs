@@ -0,0 +1,38 @@
+use sea_orm::prelude::Decimal;
+
+use super::public_id::PublicId;
+
+dto! {
+    #[derive(Clone, Default)]
+    pub struct CollectionRule {
+        #[serde(default)]
+        pub min_price: Option<Decimal>,
+        #[serde(default)]
+        pub max_price: Option<Decimal>,
+        /// Case-insensitive substring of the product title
+        #[serde(default)]
+        pub title_contains: Option<String>,
+    }
+}
+
+dto! {
+    ///
+    /// A storefront product set. Products are included if they are listed in `product_ids` or
+    /// match every condition of `rule`
+    #[derive(Clone)]
+    pub struct CollectionDetails {
+        pub title: String,
+        #[serde(default)]
+        pub product_ids: Vec<PublicId>,
+        #[serde(default)]
+        pub rule: Option<CollectionRule>,
+    }
+}
+
+dto! {
+    pub struct CollectionReturn {
+        pub slug: String,
+        #[serde(flatten)]
+        pub details: CollectionDetails,
+    }
+}
@@ -0,0 +1,28 @@
+dto! {
+    #[derive(Clone)]
+    pub struct ExperimentVariant {
+        pub name: String,
+        /// Relative share of users placed in this variant
+        pub weight: u32,
+    }
+}
+
+dto! {
+    #[derive(Clone)]
+    pub struct Experiment {
+        pub key: String,
+        /// Inactive experiments are not assigned to anyone
+        pub active: bool,
+        /// Only members of this user segment are enrolled, if set
+        #[serde(default)]
+        pub segment: Option<String>,
+        pub variants: Vec<ExperimentVariant>,
+    }
+}
+
+dto! {
+    pub struct ExperimentAssignment {
+        pub experiment: String,
+        pub variant: String,
+    }
+}
@@ -0,0 +1,51 @@
+use rocket::mtls::Certificate;
+use rocket::outcome::Outcome;
+use rocket::request::{self, FromRequest};
+use rocket::Request;
+
+use crate::config::Config;
+
+const INTERNAL_AUTH_HEADER: &'static str = "X-Internal-Auth";
+
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+///
+/// Request guard for routes meant for trusted internal callers. When `INTERNAL_AUTH_ENABLED` is
+/// set, the caller must send the `INTERNAL_AUTH_SECRET` in the `X-Internal-Auth` header or present
+/// a client certificate verified against the CA configured in `tls.mutual`. When disabled, every
+/// request is let through
+pub struct InternalCaller;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for InternalCaller {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        use rocket::http::Status;
+        let config = match req.rocket().state::<Config>() {
+            Some(config) => config,
+            None => return Outcome::Failure((Status::InternalServerError, ())),
+        };
+
+        if !config.internal_auth_enabled {
+            return Outcome::Success(Self);
+        }
+
+        if let Some(ref secret) = config.internal_auth_secret {
+            let provided = req.headers().get_one(INTERNAL_AUTH_HEADER);
+            if let Some(provided) = provided {
+                if constant_time_eq(provided.as_bytes(), secret.as_bytes()) {
+                    return Outcome::Success(Self);
+                }
+            }
+        }
+
+        if req.guard::<Certificate<'_>>().await.succeeded().is_some() {
+            return Outcome::Success(Self);
+        }
+
+        Outcome::Failure((Status::Forbidden, ()))
+    }
+}
@@ -0,0 +1,41 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeywordAction {
+    /// Reject the text
+    Block,
+    /// Accept the text but log it for moderators
+    Flag,
+    /// Swap the keyword for the rule's replacement
+    Replace,
+}
+
+dto! {
+    #[derive(Clone)]
+    pub struct KeywordRule {
+        /// Matched case-insensitively against whole words
+        pub keyword: String,
+        pub action: KeywordAction,
+        /// Used by `replace` rules; defaults to asterisks the length of the keyword
+        #[serde(default)]
+        pub replacement: Option<String>,
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum KeywordOutcome {
+    Allowed,
+    Flagged,
+    Blocked,
+}
+
+dto! {
+    pub struct KeywordCheck {
+        pub outcome: KeywordOutcome,
+        /// The text after replacements
+        pub text: String,
+        pub matched: Vec<String>,
+    }
+}
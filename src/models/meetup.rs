@@ -0,0 +1,29 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "camelCase")]
+pub enum SpotKind {
+    PoliceStation,
+    PublicPlace,
+}
+
+dto! {
+    #[derive(Clone)]
+    pub struct MeetupSpot {
+        pub name: String,
+        pub kind: SpotKind,
+        pub address: String,
+        pub latitude: f64,
+        pub longitude: f64,
+        /// Only verified spots are suggested to users
+        pub verified: bool,
+    }
+}
+
+dto! {
+    pub struct MeetupSuggestion {
+        #[serde(flatten)]
+        pub spot: MeetupSpot,
+        pub distance_km: f64,
+    }
+}
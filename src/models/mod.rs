@@ -1,3 +1,28 @@
+///
+/// Declares a request or response body. Every DTO goes through this so the frontend always sees
+/// camelCase keys
+macro_rules! dto {
+    ($(#[$meta:meta])* $vis:vis struct $name:ident $fields:tt) => {
+        #[derive(serde::Serialize, serde::Deserialize, Debug)]
+        #[serde(rename_all = "camelCase")]
+        $(#[$meta])*
+        $vis struct $name $fields
+    };
+}
+pub(crate) use dto;
+
+pub mod announcement;
+pub mod bulk;
+pub mod collection;
+pub mod experiment;
+pub mod internal_caller;
+pub mod keyword_policy;
+pub mod meetup;
+pub mod patch;
+pub mod public_id;
+pub mod ranking;
 pub mod role;
+pub mod scope;
+pub mod segment;
 pub mod user;
-pub mod product;
\ No newline at end of file
+pub mod product;
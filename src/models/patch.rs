@@ -0,0 +1,62 @@
+use sea_orm::{ActiveValue, Value};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+///
+/// A field of a partial update. `Missing` leaves the column alone, `Null` clears it, and `Value`
+/// sets it. Fields of this type need `#[serde(default)]` so an absent key becomes `Missing`
+#[derive(Debug, Default)]
+pub enum Patch<T> {
+    #[default]
+    Missing,
+    Null,
+    Value(T),
+}
+
+impl<T> Patch<T> {
+    ///
+    /// Applies the patch to a nullable column
+    pub fn apply(self, column: &mut ActiveValue<Option<T>>)
+    where
+        Option<T>: Into<Value>,
+    {
+        match self {
+            Self::Missing => {}
+            Self::Null => *column = ActiveValue::Set(None),
+            Self::Value(value) => *column = ActiveValue::Set(Some(value)),
+        }
+    }
+
+    ///
+    /// Applies the patch to a column that cannot be null. Returns `Err` if the patch is `Null`
+    pub fn apply_required(self, column: &mut ActiveValue<T>) -> Result<(), ()>
+    where
+        T: Into<Value>,
+    {
+        match self {
+            Self::Missing => Ok(()),
+            Self::Null => Err(()),
+            Self::Value(value) => {
+                *column = ActiveValue::Set(value);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Patch<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match Option::<T>::deserialize(deserializer)? {
+            Some(value) => Self::Value(value),
+            None => Self::Null,
+        })
+    }
+}
+
+impl<T: Serialize> Serialize for Patch<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Value(value) => value.serialize(serializer),
+            Self::Missing | Self::Null => serializer.serialize_none(),
+        }
+    }
+}
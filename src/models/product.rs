@@ -1,25 +1,153 @@
-use super::user::MinUserReturnDto;
+use super::{patch::Patch, public_id::PublicId, user::MinUserReturnDto};
+use entity::product::Model as ProductModel;
 use sea_orm::prelude::Decimal;
-use serde::{Deserialize, Serialize};
-
-#[derive(Serialize, Deserialize, Debug)]
-pub struct ProductDetails {
-    pub description: String,
-    pub title: String,
-    pub price: Decimal,
-    pub country: String,
-    pub state: String,
-    pub city: String,
-    pub zip: String,
-    pub latitude: Option<Decimal>,
-    pub longitude: Option<Decimal>
-}
-
-#[derive(Serialize, Deserialize, Debug)]
-#[serde(rename_all = "camelCase")]
-pub struct ProductReturn {
-    pub title: String,
-    pub description: String,
-    pub price: f64,
-    pub created_by: MinUserReturnDto,
+
+dto! {
+    pub struct ProductDetails {
+        pub description: String,
+        pub title: String,
+        pub price: Decimal,
+        pub country: String,
+        pub state: String,
+        pub city: String,
+        pub zip: String,
+        pub latitude: Option<Decimal>,
+        pub longitude: Option<Decimal>
+    }
+}
+
+dto! {
+    ///
+    /// Body of a product PATCH. Only the fields present in the request are changed
+    pub struct ProductPatch {
+        #[serde(default)]
+        pub description: Patch<String>,
+        #[serde(default)]
+        pub title: Patch<String>,
+        #[serde(default)]
+        pub price: Patch<Decimal>,
+        #[serde(default)]
+        pub country: Patch<String>,
+        #[serde(default)]
+        pub state: Patch<String>,
+        #[serde(default)]
+        pub city: Patch<String>,
+        #[serde(default)]
+        pub zip: Patch<String>,
+        #[serde(default)]
+        pub latitude: Patch<Decimal>,
+        #[serde(default)]
+        pub longitude: Patch<Decimal>,
+    }
+}
+
+dto! {
+    pub struct ProductReturn {
+        pub id: PublicId,
+        pub title: String,
+        pub description: String,
+        /// `description` rendered from markdown and sanitized
+        pub description_html: String,
+        pub price: f64,
+        pub created_by: MinUserReturnDto,
+    }
+}
+
+const MIN_TITLE_LENGTH: usize = 15;
+const MIN_DESCRIPTION_LENGTH: usize = 150;
+
+dto! {
+    pub struct ProductQuality {
+        /// Listing completeness from 0 to 100
+        pub score: u8,
+        pub hints: Vec<String>,
+    }
+}
+
+impl From<&ProductModel> for ProductQuality {
+    fn from(product: &ProductModel) -> Self {
+        let checks: [(bool, u8, &str); 5] = [
+            (
+                product.product_title.trim().chars().count() >= MIN_TITLE_LENGTH,
+                20,
+                "Use a more descriptive title, including brand and model",
+            ),
+            (
+                product.description.trim().chars().count() >= MIN_DESCRIPTION_LENGTH,
+                30,
+                "Describe the item's condition, specs, and what's included",
+            ),
+            (
+                product.price > Decimal::ZERO,
+                10,
+                "Set a price so buyers know what to offer",
+            ),
+            (
+                !product.location_city.trim().is_empty()
+                    && !product.location_state.trim().is_empty()
+                    && !product.location_zip.trim().is_empty(),
+                20,
+                "Add your city, state, and zip code",
+            ),
+            (
+                product.location_latitude.is_some() && product.location_longitude.is_some(),
+                20,
+                "Add a map location so nearby buyers can find the listing",
+            ),
+        ];
+
+        let mut quality = Self {
+            score: 0,
+            hints: Vec::new(),
+        };
+        for (passed, weight, hint) in checks {
+            if passed {
+                quality.score += weight;
+            } else {
+                quality.hints.push(hint.to_owned());
+            }
+        }
+
+        quality
+    }
+}
+
+dto! {
+    pub struct ComparedAttribute {
+        pub name: String,
+        /// One value per compared product, in the same order as `ProductComparison::products`
+        pub values: Vec<serde_json::Value>,
+    }
+}
+
+dto! {
+    ///
+    /// Side-by-side attribute matrix for a set of products
+    pub struct ProductComparison {
+        pub products: Vec<PublicId>,
+        pub attributes: Vec<ComparedAttribute>,
+    }
+}
+
+impl From<&[ProductModel]> for ProductComparison {
+    fn from(products: &[ProductModel]) -> Self {
+        let attribute =
+            |name: &str, value: fn(&ProductModel) -> serde_json::Value| ComparedAttribute {
+                name: name.to_owned(),
+                values: products.iter().map(value).collect(),
+            };
+
+        Self {
+            products: products.iter().map(|product| PublicId(product.id)).collect(),
+            attributes: vec![
+                attribute("title", |p| p.product_title.clone().into()),
+                attribute("price", |p| f64::try_from(p.price).ok().into()),
+                attribute("city", |p| p.location_city.clone().into()),
+                attribute("state", |p| p.location_state.clone().into()),
+                attribute("country", |p| p.location_country.clone().into()),
+                attribute("listedAt", |p| p.created_at.to_string().into()),
+                attribute("qualityScore", |p| ProductQuality::from(p).score.into()),
+            ],
+        }
+    }
 }
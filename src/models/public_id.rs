@@ -0,0 +1,128 @@
+use hmac::{Hmac, Mac};
+use rocket::request::FromParam;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::Sha512;
+use std::{fmt, str::FromStr, sync::OnceLock};
+
+use crate::config::Config;
+
+const ALPHABET: &[u8; 62] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+/// Enough base62 digits for any u64
+const ENCODED_LENGTH: usize = 11;
+const FEISTEL_ROUNDS: u8 = 4;
+
+static KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+///
+/// Database id as shown to clients. The id is run through a keyed permutation and base62 encoded,
+/// so public ids are not sequential and cannot be guessed without `PUBLIC_ID_SECRET`. Internally
+/// the plain i64 is still used for lookups and joins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PublicId(pub i64);
+
+///
+/// Sets the key public ids are derived from. Called at startup; later calls keep the first key,
+/// so building several rockets in one process is fine
+pub fn init(config: &Config) {
+    KEY.get_or_init(|| config.public_id_secret.as_bytes().to_vec());
+}
+
+///
+/// The key set by `init`, or `PUBLIC_ID_SECRET` when an id is used first. `Config::from_env`
+/// requires that variable, so the fallback only fails if config loading was skipped too
+fn key() -> &'static [u8] {
+    KEY.get_or_init(|| {
+        Config::public_id_secret_from_env()
+            .expect("PUBLIC_ID_SECRET is not set")
+            .into_bytes()
+    })
+}
+
+fn round(round: u8, half: u32) -> u32 {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key()).unwrap();
+    mac.update(&[round]);
+    mac.update(&half.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+
+    u32::from_be_bytes([digest[0], digest[1], digest[2], digest[3]])
+}
+
+fn permute(value: u64) -> u64 {
+    let (mut left, mut right) = ((value >> 32) as u32, value as u32);
+    for r in 0..FEISTEL_ROUNDS {
+        (left, right) = (right, left ^ round(r, right));
+    }
+
+    (left as u64) << 32 | right as u64
+}
+
+fn unpermute(value: u64) -> u64 {
+    let (mut left, mut right) = ((value >> 32) as u32, value as u32);
+    for r in (0..FEISTEL_ROUNDS).rev() {
+        (left, right) = (right ^ round(r, left), left);
+    }
+
+    (left as u64) << 32 | right as u64
+}
+
+impl fmt::Display for PublicId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut value = permute(self.0 as u64);
+        let mut encoded = [ALPHABET[0]; ENCODED_LENGTH];
+        for digit in encoded.iter_mut().rev() {
+            *digit = ALPHABET[(value % 62) as usize];
+            value /= 62;
+        }
+
+        f.write_str(std::str::from_utf8(&encoded).unwrap())
+    }
+}
+
+impl FromStr for PublicId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ENCODED_LENGTH {
+            return Err(());
+        }
+        let mut value: u64 = 0;
+        for c in s.bytes() {
+            let digit = ALPHABET.iter().position(|a| *a == c).ok_or(())? as u64;
+            value = value
+                .checked_mul(62)
+                .and_then(|v| v.checked_add(digit))
+                .ok_or(())?;
+        }
+
+        i64::try_from(unpermute(value)).map(Self).map_err(|_| ())
+    }
+}
+
+impl<'a> FromParam<'a> for PublicId {
+    type Error = ();
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        param.parse()
+    }
+}
+
+impl From<i64> for PublicId {
+    fn from(id: i64) -> Self {
+        Self(id)
+    }
+}
+
+impl Serialize for PublicId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        encoded
+            .parse()
+            .map_err(|_| de::Error::custom(format!("invalid id {encoded:?}")))
+    }
+}
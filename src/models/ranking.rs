@@ -0,0 +1,58 @@
+use sea_orm::FromQueryResult;
+
+dto! {
+    #[derive(Clone, Copy)]
+    pub struct RankingWeights {
+        /// Minimum pg_trgm word similarity for a product title to be considered a search match
+        pub similarity_threshold: f64,
+        /// Weight of title similarity in the search score
+        pub similarity_weight: f64,
+        /// Weight of listing recency in the search score
+        pub recency_weight: f64,
+        /// Age in days at which a listing's recency score has dropped to half
+        pub recency_half_life_days: f64,
+    }
+}
+
+impl Default for RankingWeights {
+    fn default() -> Self {
+        Self {
+            similarity_threshold: 0.3,
+            similarity_weight: 0.8,
+            recency_weight: 0.2,
+            recency_half_life_days: 30.0,
+        }
+    }
+}
+
+#[derive(FromQueryResult)]
+pub struct ScoreParts {
+    pub similarity: f64,
+    pub recency: f64,
+}
+
+dto! {
+    ///
+    /// How a listing's search score for a query is put together
+    pub struct ScoreExplanation {
+        pub similarity: f64,
+        pub recency: f64,
+        pub weights: RankingWeights,
+        pub score: f64,
+        /// Whether the listing is similar enough to the query to appear in results at all
+        pub matches: bool,
+    }
+}
+
+impl ScoreExplanation {
+    pub fn new(parts: ScoreParts, weights: RankingWeights) -> Self {
+        Self {
+            similarity: parts.similarity,
+            recency: parts.recency,
+            score: parts.similarity * weights.similarity_weight
+                + parts.recency * weights.recency_weight,
+            matches: parts.similarity >= weights.similarity_threshold,
+            weights,
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use rocket::outcome::{try_outcome, Outcome};
+use rocket::request::{self, FromRequest};
+use rocket::Request;
+use std::marker::PhantomData;
+
+use super::user::AuthUser;
+
+pub const PRODUCTS_READ: &'static str = "products:read";
+pub const PRODUCTS_WRITE: &'static str = "products:write";
+pub const USERS_READ: &'static str = "users:read";
+
+///
+/// Scopes granted to tokens issued from a password login
+pub const ALL_SCOPES: &[&'static str] = &[PRODUCTS_READ, PRODUCTS_WRITE, USERS_READ];
+
+pub trait Scope {
+    const NAME: &'static str;
+}
+
+pub struct ProductsRead;
+
+impl Scope for ProductsRead {
+    const NAME: &'static str = PRODUCTS_READ;
+}
+
+pub struct ProductsWrite;
+
+impl Scope for ProductsWrite {
+    const NAME: &'static str = PRODUCTS_WRITE;
+}
+
+pub struct UsersRead;
+
+impl Scope for UsersRead {
+    const NAME: &'static str = USERS_READ;
+}
+
+///
+/// Request guard that authenticates like `AuthUser` and additionally requires the token to carry
+/// the scope `S`, e.g. `Scoped<ProductsWrite>`
+pub struct Scoped<S: Scope> {
+    pub auth_user: AuthUser,
+    scope: PhantomData<S>,
+}
+
+#[rocket::async_trait]
+impl<'r, S: Scope> FromRequest<'r> for Scoped<S> {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        use rocket::http::Status;
+        let auth_user = try_outcome!(AuthUser::from_request(req).await);
+
+        if !auth_user.has_scope(S::NAME) {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
+        Outcome::Success(Self {
+            auth_user,
+            scope: PhantomData,
+        })
+    }
+}
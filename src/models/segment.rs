@@ -0,0 +1,31 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use super::role::Role;
+
+///
+/// A composable condition on users. Listing predicates count every product the user has listed
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type", content = "value", rename_all = "camelCase")]
+pub enum SegmentPredicate {
+    All(Vec<SegmentPredicate>),
+    Any(Vec<SegmentPredicate>),
+    Not(Box<SegmentPredicate>),
+    SignedUpAfter(NaiveDateTime),
+    SignedUpBefore(NaiveDateTime),
+    MinListings(u64),
+    MaxListings(u64),
+    Role(Role),
+    /// Has listed at least one product in the given state or region
+    ListsIn(String),
+}
+
+dto! {
+    pub struct SegmentReturn {
+        pub name: String,
+        pub predicate: SegmentPredicate,
+        /// None until the segment has been materialized
+        pub member_count: Option<usize>,
+        pub materialized_at: Option<NaiveDateTime>,
+    }
+}
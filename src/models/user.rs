@@ -1,15 +1,21 @@
 use chrono::NaiveDateTime;
 use entity::user::Model as UserModel;
-use rocket::outcome::Outcome;
+use rocket::outcome::{try_outcome, Outcome};
 use rocket::request::{self, FromRequest};
 use rocket::Request;
 use serde::{Deserialize, Serialize};
 
-use crate::services::{UserService, UserServiceError};
+use crate::{
+    config::{AuthMode, Config},
+    services::{UserService, UserServiceError},
+};
 
-use super::role::Role;
+use super::{public_id::PublicId, role::Role};
 
 pub const ADMIN_USERNAME: &'static str = "admin";
+/// Cookie holding the JWT in cookie auth mode
+pub const TOKEN_COOKIE: &'static str = "token";
+const AUTH_MODE_HEADER: &'static str = "X-Auth-Mode";
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct User {
@@ -38,38 +44,75 @@ impl TryFrom<UserModel> for User {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserRegister {
-    pub username: String,
-    pub email: String,
-    pub password: String,
+dto! {
+    pub struct UserRegister {
+        pub username: String,
+        pub email: String,
+        pub password: String,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserLogin {
-    pub username: Option<String>,
-    pub email: Option<String>,
-    pub password: String,
+dto! {
+    pub struct UserLogin {
+        pub username: Option<String>,
+        pub email: Option<String>,
+        pub password: String,
+    }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct UserReturnDto {
-    pub id: i64,
-    pub username: String,
-    pub email: String,
-    pub role: Role,
+dto! {
+    pub struct UserReturnDto {
+        pub id: PublicId,
+        pub username: String,
+        pub email: String,
+        pub role: Role,
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-pub struct MinUserReturnDto {
-    pub id: i64,
-    pub username: String
+dto! {
+    pub struct MinUserReturnDto {
+        pub id: PublicId,
+        pub username: String
+    }
+}
+
+///
+/// Request guard resolving how the calling client wants to authenticate, either `Cookie` or
+/// `Bearer`. Only meaningful when `AUTH_MODE` is `both`; otherwise it is the configured mode
+pub struct ClientAuthMode(pub AuthMode);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientAuthMode {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let configured = match req.rocket().state::<Config>() {
+            Some(config) => config.auth_mode,
+            None => return Outcome::Failure((rocket::http::Status::InternalServerError, ())),
+        };
+
+        let mode = match (configured, req.headers().get_one(AUTH_MODE_HEADER)) {
+            (AuthMode::Both, Some("bearer")) => AuthMode::Bearer,
+            (AuthMode::Both, _) => AuthMode::Cookie,
+            (mode, _) => mode,
+        };
+
+        Outcome::Success(Self(mode))
+    }
 }
 
 ///
-/// Request guard that will read the JWT from cookies and inject the user into the function
+/// Request guard that will read the JWT from the `Authorization` header or cookies, depending on
+/// `AUTH_MODE`, and inject the user into the function
 pub struct AuthUser {
     pub user: User,
+    pub scopes: Vec<String>,
+}
+
+impl AuthUser {
+    pub fn has_scope(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|granted| granted == scope)
+    }
 }
 
 #[rocket::async_trait]
@@ -78,7 +121,22 @@ impl<'r> FromRequest<'r> for AuthUser {
 
     async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
         use rocket::http::Status;
-        let token = req.cookies().get("token").map(|cookie| cookie.value());
+        let auth_mode = req
+            .rocket()
+            .state::<Config>()
+            .map(|config| config.auth_mode)
+            .unwrap_or(AuthMode::Cookie);
+        let bearer = req
+            .headers()
+            .get_one("Authorization")
+            .and_then(|header| header.strip_prefix("Bearer "))
+            .filter(|_| auth_mode.allows_bearer());
+        let cookie = req
+            .cookies()
+            .get(TOKEN_COOKIE)
+            .map(|cookie| cookie.value())
+            .filter(|_| auth_mode.allows_cookie());
+        let token = bearer.or(cookie);
         if let None = token {
             return Outcome::Failure((Status::Unauthorized, ()));
         }
@@ -90,13 +148,14 @@ impl<'r> FromRequest<'r> for AuthUser {
         let mut user_service = user_service.unwrap();
 
         match user_service.validate_token(token).await {
-            Ok(user) => {
+            Ok((user, scopes)) => {
                 let user = User::try_from(user);
                 if let Err(_) = user {
                     return Outcome::Failure((Status::InternalServerError, ()));
                 }
                 return Outcome::Success(Self {
                     user: user.unwrap(),
+                    scopes,
                 });
             }
             Err(e) => match e {
@@ -108,3 +167,27 @@ impl<'r> FromRequest<'r> for AuthUser {
         }
     }
 }
+
+///
+/// Request guard that only lets through authenticated users with the admin role
+pub struct AdminUser {
+    pub user: User,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminUser {
+    type Error = ();
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        use rocket::http::Status;
+        let auth_user = try_outcome!(AuthUser::from_request(req).await);
+
+        if !matches!(auth_user.user.role, Role::Admin) {
+            return Outcome::Failure((Status::Forbidden, ()));
+        }
+
+        Outcome::Success(Self {
+            user: auth_user.user,
+        })
+    }
+}
@@ -0,0 +1,143 @@
+use chrono::Utc;
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    models::announcement::{AnnouncementDetails, AnnouncementReturn},
+    services::write_json_atomically,
+};
+
+#[derive(Error, Debug)]
+pub enum AnnouncementBoardError {
+    #[error("Unable to read or write announcements file: {0}")]
+    AnnouncementsFile(std::io::Error),
+    #[error("Announcements file is not valid: {0}")]
+    InvalidAnnouncementsFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for AnnouncementBoardError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        println!("{self}");
+        Response::build().status(Status::InternalServerError).ok()
+    }
+}
+
+#[derive(Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BoardState {
+    next_id: u64,
+    announcements: BTreeMap<u64, AnnouncementDetails>,
+}
+
+///
+/// Banners shown by the frontend for maintenance windows and feature news. Announcements are
+/// saved to `ANNOUNCEMENTS_FILE` when that is set, and are otherwise lost on restart
+pub struct AnnouncementBoard {
+    state: RwLock<BoardState>,
+    announcements_file: Option<PathBuf>,
+}
+
+impl AnnouncementBoard {
+    pub fn from_config(config: &Config) -> Result<Self, AnnouncementBoardError> {
+        let announcements_file = config.announcements_file.as_ref().map(PathBuf::from);
+        let state = match announcements_file {
+            Some(ref path) if path.exists() => {
+                let contents =
+                    fs::read_to_string(path).map_err(AnnouncementBoardError::AnnouncementsFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| AnnouncementBoardError::InvalidAnnouncementsFile(e.to_string()))?
+            }
+            _ => BoardState::default(),
+        };
+
+        Ok(Self {
+            state: RwLock::new(state),
+            announcements_file,
+        })
+    }
+
+    pub fn all(&self) -> Vec<AnnouncementReturn> {
+        self.state
+            .read()
+            .unwrap()
+            .announcements
+            .iter()
+            .map(|(id, details)| AnnouncementReturn {
+                id: *id,
+                details: details.clone(),
+            })
+            .collect()
+    }
+
+    ///
+    /// Announcements whose window includes now and whose audience includes the caller
+    pub fn active(&self, signed_in: bool) -> Vec<AnnouncementReturn> {
+        let now = Utc::now();
+        self.all()
+            .into_iter()
+            .filter(|announcement| {
+                let details = &announcement.details;
+                details.starts_at <= now
+                    && details.ends_at.is_none_or(|ends_at| now < ends_at)
+                    && details.audience.includes(signed_in)
+            })
+            .collect()
+    }
+
+    ///
+    /// Adds an announcement and returns its id. Like `update` and `remove`, the change is saved
+    /// before it is applied, so a failed write leaves the current announcements in place
+    pub fn create(&self, details: AnnouncementDetails) -> Result<u64, AnnouncementBoardError> {
+        let mut state = self.state.write().unwrap();
+        let mut updated = state.clone();
+        updated.next_id += 1;
+        let id = updated.next_id;
+        updated.announcements.insert(id, details);
+
+        self.save(&updated)?;
+        *state = updated;
+        Ok(id)
+    }
+
+    ///
+    /// Replaces an announcement. Returns false if there is no announcement with the id
+    pub fn update(
+        &self,
+        id: u64,
+        details: AnnouncementDetails,
+    ) -> Result<bool, AnnouncementBoardError> {
+        let mut state = self.state.write().unwrap();
+        let mut updated = state.clone();
+        match updated.announcements.get_mut(&id) {
+            Some(existing) => *existing = details,
+            None => return Ok(false),
+        }
+
+        self.save(&updated)?;
+        *state = updated;
+        Ok(true)
+    }
+
+    pub fn remove(&self, id: u64) -> Result<bool, AnnouncementBoardError> {
+        let mut state = self.state.write().unwrap();
+        let mut updated = state.clone();
+        if updated.announcements.remove(&id).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&updated)?;
+        *state = updated;
+        Ok(true)
+    }
+
+    fn save(&self, state: &BoardState) -> Result<(), AnnouncementBoardError> {
+        match self.announcements_file {
+            Some(ref path) => write_json_atomically(path, state)
+                .map_err(AnnouncementBoardError::AnnouncementsFile),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use rocket::{
+    futures::Stream,
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromParam, FromRequest},
+    response::Responder,
+    tokio::io::{AsyncBufRead, AsyncBufReadExt},
+    Request, Response,
+};
+use sea_orm::{
+    entity::prelude::*, ConnectionTrait, DatabaseBackend, DatabaseConnection, JsonValue,
+    QueryOrder, Statement, TransactionTrait,
+};
+use serde_json::json;
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    db::{establish_connection, DbError},
+};
+
+#[derive(Error, Debug)]
+pub enum BackupServiceError {
+    #[error(transparent)]
+    DbError(DbError),
+    #[error(transparent)]
+    OrmError(sea_orm::DbErr),
+    #[error("Unable to read import body: {0}")]
+    ReadError(std::io::Error),
+    #[error("Line {0} could not be imported: {1}")]
+    InvalidRow(usize, String),
+    #[error("Imports are limited to {0} MiB")]
+    TooLarge(u64),
+}
+
+impl<'r> Responder<'r, 'static> for BackupServiceError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidRow(..) | Self::ReadError(_) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            Self::TooLarge(_) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::PayloadTooLarge)
+                    .ok()
+            }
+            _ => Response::build().status(Status::InternalServerError).ok(),
+        }
+    }
+}
+
+///
+/// A table included in backups. Imports should be run in declaration order, since products
+/// reference users
+#[derive(Clone, Copy, Debug)]
+pub enum BackupTable {
+    Users,
+    Products,
+}
+
+impl BackupTable {
+    fn table_name(self) -> &'static str {
+        match self {
+            Self::Users => entity::user::Entity.table_name(),
+            Self::Products => entity::product::Entity.table_name(),
+        }
+    }
+}
+
+impl<'a> FromParam<'a> for BackupTable {
+    type Error = &'a str;
+
+    fn from_param(param: &'a str) -> Result<Self, Self::Error> {
+        match param {
+            "users" => Ok(Self::Users),
+            "products" => Ok(Self::Products),
+            _ => Err(param),
+        }
+    }
+}
+
+///
+/// Dumps and restores whole tables as newline-delimited JSON, one row per line with the raw
+/// column values, for cloning environments and disaster recovery drills
+pub struct BackupService {
+    db_connection: DatabaseConnection,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for BackupService {
+    type Error = BackupServiceError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.rocket().state::<Config>() {
+            Some(config) => config,
+            None => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    BackupServiceError::DbError(DbError::ConfigError),
+                ))
+            }
+        };
+
+        match establish_connection(config).await {
+            Ok(db) => Outcome::Success(Self { db_connection: db }),
+            Err(e) => {
+                Outcome::Failure((Status::InternalServerError, BackupServiceError::DbError(e)))
+            }
+        }
+    }
+}
+
+impl BackupService {
+    ///
+    /// Every row of `table` in primary key order, read as the stream is polled
+    pub async fn stream_table(
+        &self,
+        table: BackupTable,
+    ) -> Result<impl Stream<Item = Result<JsonValue, DbErr>> + '_, BackupServiceError> {
+        let found = match table {
+            BackupTable::Users => {
+                entity::user::Entity::find()
+                    .order_by_asc(entity::user::Column::Id)
+                    .into_json()
+                    .stream(&self.db_connection)
+                    .await
+            }
+            BackupTable::Products => {
+                entity::product::Entity::find()
+                    .order_by_asc(entity::product::Column::Id)
+                    .into_json()
+                    .stream(&self.db_connection)
+                    .await
+            }
+        };
+
+        found.map_err(BackupServiceError::OrmError)
+    }
+
+    ///
+    /// Inserts every line of `rows` into `table` in one transaction, then moves the id sequence
+    /// past the imported ids. Returns the number of rows imported
+    pub async fn import_table<R>(
+        &self,
+        table: BackupTable,
+        rows: R,
+    ) -> Result<u64, BackupServiceError>
+    where
+        R: AsyncBufRead + Unpin,
+    {
+        let table_name = table.table_name();
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(BackupServiceError::OrmError)?;
+
+        let mut lines = rows.lines();
+        let mut line_number = 0;
+        let mut imported = 0;
+        while let Some(line) = lines
+            .next_line()
+            .await
+            .map_err(BackupServiceError::ReadError)?
+        {
+            line_number += 1;
+            if line.trim().is_empty() {
+                continue;
+            }
+            if let Err(e) = serde_json::from_str::<JsonValue>(&line) {
+                return Err(BackupServiceError::InvalidRow(line_number, e.to_string()));
+            }
+
+            txn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::Postgres,
+                &format!(
+                    "INSERT INTO \"{table_name}\" SELECT * FROM json_populate_record(NULL::\"{table_name}\", $1::json)"
+                ),
+                vec![line.into()],
+            ))
+            .await
+            .map_err(|e| BackupServiceError::InvalidRow(line_number, e.to_string()))?;
+            imported += 1;
+        }
+
+        txn.execute(Statement::from_string(
+            DatabaseBackend::Postgres,
+            format!(
+                "SELECT setval(pg_get_serial_sequence('\"{table_name}\"', 'id'), \
+                COALESCE((SELECT MAX(id) FROM \"{table_name}\"), 0) + 1, false)"
+            ),
+        ))
+        .await
+        .map_err(BackupServiceError::OrmError)?;
+
+        txn.commit().await.map_err(BackupServiceError::OrmError)?;
+
+        Ok(imported)
+    }
+}
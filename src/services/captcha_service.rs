@@ -0,0 +1,285 @@
+use rocket::{
+    http::Status,
+    outcome::Outcome,
+    request::{self, FromRequest},
+    response::Responder,
+    Request, Response,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+use crate::config::{CaptchaProvider, Config};
+
+const HCAPTCHA_VERIFY_URL: &'static str = "https://hcaptcha.com/siteverify";
+const TURNSTILE_VERIFY_URL: &'static str =
+    "https://challenges.cloudflare.com/turnstile/v0/siteverify";
+const CAPTCHA_TOKEN_HEADER: &'static str = "X-Captcha-Token";
+/// Number of failed logins for an account before a CAPTCHA is required to try again
+const FAILED_LOGINS_BEFORE_CAPTCHA: u32 = 3;
+/// Failed logins are forgotten once an account has gone this long without another one
+const FAILED_LOGIN_WINDOW: Duration = Duration::from_secs(15 * 60);
+/// Most accounts whose failed logins are tracked at once
+const MAX_TRACKED_LOGINS: usize = 10_000;
+
+#[derive(Error, Debug)]
+pub enum CaptchaError {
+    #[error("A CAPTCHA token is required for this request")]
+    MissingToken,
+    #[error("CAPTCHA verification failed")]
+    VerificationFailed,
+    #[error("CAPTCHA provider could not be reached")]
+    ProviderUnavailable,
+    #[error("CAPTCHA verification is not configured correctly")]
+    NotConfigured,
+}
+
+impl<'r> Responder<'r, 'static> for CaptchaError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::MissingToken | Self::VerificationFailed => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            Self::ProviderUnavailable | Self::NotConfigured => {
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+#[rocket::async_trait]
+pub trait CaptchaVerifier: Send + Sync {
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, CaptchaError>;
+
+    ///
+    /// Whether requests need a CAPTCHA token at all
+    fn is_enabled(&self) -> bool {
+        true
+    }
+}
+
+///
+/// Accepts every token. Used when no provider is configured, e.g. in development and tests
+pub struct DisabledCaptchaVerifier;
+
+#[rocket::async_trait]
+impl CaptchaVerifier for DisabledCaptchaVerifier {
+    async fn verify(&self, _: &str, _: Option<IpAddr>) -> Result<bool, CaptchaError> {
+        Ok(true)
+    }
+
+    fn is_enabled(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+}
+
+///
+/// Verifier for providers implementing the `siteverify` form API shared by hCaptcha and Turnstile
+pub struct SiteVerifyCaptchaVerifier {
+    verify_url: &'static str,
+    secret: String,
+    client: reqwest::Client,
+}
+
+impl SiteVerifyCaptchaVerifier {
+    pub fn hcaptcha(secret: String) -> Self {
+        Self {
+            verify_url: HCAPTCHA_VERIFY_URL,
+            secret,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn turnstile(secret: String) -> Self {
+        Self {
+            verify_url: TURNSTILE_VERIFY_URL,
+            secret,
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl CaptchaVerifier for SiteVerifyCaptchaVerifier {
+    async fn verify(&self, token: &str, remote_ip: Option<IpAddr>) -> Result<bool, CaptchaError> {
+        let mut params = vec![
+            ("secret", self.secret.clone()),
+            ("response", token.to_owned()),
+        ];
+        if let Some(ip) = remote_ip {
+            params.push(("remoteip", ip.to_string()));
+        }
+
+        let body = self
+            .client
+            .post(self.verify_url)
+            .form(&params)
+            .send()
+            .await
+            .map_err(|_| CaptchaError::ProviderUnavailable)?
+            .bytes()
+            .await
+            .map_err(|_| CaptchaError::ProviderUnavailable)?;
+
+        let verified: SiteVerifyResponse =
+            serde_json::from_slice(&body).map_err(|_| CaptchaError::ProviderUnavailable)?;
+
+        Ok(verified.success)
+    }
+}
+
+///
+/// Builds the verifier selected by `CAPTCHA_PROVIDER`. CAPTCHA checks are disabled when no
+/// provider is set
+pub fn captcha_verifier_from_config(config: &Config) -> Box<dyn CaptchaVerifier> {
+    match config.captcha_provider {
+        CaptchaProvider::Disabled => Box::new(DisabledCaptchaVerifier),
+        CaptchaProvider::HCaptcha { ref secret } => {
+            Box::new(SiteVerifyCaptchaVerifier::hcaptcha(secret.clone()))
+        }
+        CaptchaProvider::Turnstile { ref secret } => {
+            Box::new(SiteVerifyCaptchaVerifier::turnstile(secret.clone()))
+        }
+    }
+}
+
+struct FailedAttempts {
+    count: u32,
+    last_failure: Instant,
+}
+
+impl FailedAttempts {
+    fn is_expired(&self, now: Instant) -> bool {
+        now.duration_since(self.last_failure) >= FAILED_LOGIN_WINDOW
+    }
+}
+
+///
+/// Failed login counts keyed by the id of the account the login resolved to, so logging in by
+/// email, by username, or by any pattern matching the same account shares one count. Only wrong
+/// passwords for existing accounts are recorded, counts expire after `FAILED_LOGIN_WINDOW`, and at
+/// most `MAX_TRACKED_LOGINS` accounts are tracked
+#[derive(Default)]
+pub struct FailedLogins {
+    attempts: Mutex<HashMap<i64, FailedAttempts>>,
+}
+
+impl FailedLogins {
+    fn requires_captcha(&self, user_id: i64) -> bool {
+        let attempts = self.attempts.lock().unwrap();
+        attempts
+            .get(&user_id)
+            .filter(|failed| !failed.is_expired(Instant::now()))
+            .map_or(0, |failed| failed.count)
+            >= FAILED_LOGINS_BEFORE_CAPTCHA
+    }
+
+    fn record_failure(&self, user_id: i64) {
+        let now = Instant::now();
+        let mut attempts = self.attempts.lock().unwrap();
+
+        if !attempts.contains_key(&user_id) && attempts.len() >= MAX_TRACKED_LOGINS {
+            attempts.retain(|_, failed| !failed.is_expired(now));
+            if attempts.len() >= MAX_TRACKED_LOGINS {
+                let oldest = attempts
+                    .iter()
+                    .min_by_key(|(_, failed)| failed.last_failure)
+                    .map(|(&user_id, _)| user_id);
+                if let Some(oldest) = oldest {
+                    attempts.remove(&oldest);
+                }
+            }
+        }
+
+        let failed = attempts.entry(user_id).or_insert(FailedAttempts {
+            count: 0,
+            last_failure: now,
+        });
+        if failed.is_expired(now) {
+            failed.count = 0;
+        }
+        failed.count += 1;
+        failed.last_failure = now;
+    }
+
+    fn clear(&self, user_id: i64) {
+        self.attempts.lock().unwrap().remove(&user_id);
+    }
+}
+
+///
+/// Request guard pairing the configured `CaptchaVerifier` with the token sent in the
+/// `X-Captcha-Token` header
+pub struct CaptchaService<'r> {
+    verifier: &'r dyn CaptchaVerifier,
+    failed_logins: &'r FailedLogins,
+    token: Option<&'r str>,
+    remote_ip: Option<IpAddr>,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CaptchaService<'r> {
+    type Error = CaptchaError;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let verifier = req.rocket().state::<Box<dyn CaptchaVerifier>>();
+        let failed_logins = req.rocket().state::<FailedLogins>();
+
+        match (verifier, failed_logins) {
+            (Some(verifier), Some(failed_logins)) => Outcome::Success(Self {
+                verifier: verifier.as_ref(),
+                failed_logins,
+                token: req.headers().get_one(CAPTCHA_TOKEN_HEADER),
+                remote_ip: req.client_ip(),
+            }),
+            _ => Outcome::Failure((Status::InternalServerError, CaptchaError::NotConfigured)),
+        }
+    }
+}
+
+impl<'r> CaptchaService<'r> {
+    pub async fn verify(&self) -> Result<(), CaptchaError> {
+        if !self.verifier.is_enabled() {
+            return Ok(());
+        }
+        let token = self.token.ok_or(CaptchaError::MissingToken)?;
+
+        if !self.verifier.verify(token, self.remote_ip).await? {
+            return Err(CaptchaError::VerificationFailed);
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Only requires a CAPTCHA once the account has had repeated failed logins. Call this before
+    /// checking the password
+    pub async fn verify_login(&self, user_id: i64) -> Result<(), CaptchaError> {
+        if self.failed_logins.requires_captcha(user_id) {
+            self.verify().await?;
+        }
+
+        Ok(())
+    }
+
+    pub fn record_failed_login(&self, user_id: i64) {
+        self.failed_logins.record_failure(user_id);
+    }
+
+    pub fn clear_failed_logins(&self, user_id: i64) {
+        self.failed_logins.clear(user_id);
+    }
+}
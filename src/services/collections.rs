@@ -0,0 +1,133 @@
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde_json::json;
+use std::{collections::BTreeMap, fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    models::collection::{CollectionDetails, CollectionReturn},
+    services::write_json_atomically,
+};
+
+#[derive(Error, Debug)]
+pub enum CollectionsError {
+    #[error("Collection slugs may only contain lowercase letters, digits, and dashes")]
+    InvalidSlug,
+    #[error("Collections must have a title")]
+    EmptyTitle,
+    #[error("Unable to read or write collections file: {0}")]
+    CollectionsFile(std::io::Error),
+    #[error("Collections file is not valid: {0}")]
+    InvalidCollectionsFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for CollectionsError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidSlug | Self::EmptyTitle => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+///
+/// Curated and rule-based product sets for the storefront home page, keyed by slug. Admin
+/// changes are saved to `COLLECTIONS_FILE` when that is set
+pub struct Collections {
+    collections: RwLock<BTreeMap<String, CollectionDetails>>,
+    collections_file: Option<PathBuf>,
+}
+
+impl Collections {
+    pub fn from_config(config: &Config) -> Result<Self, CollectionsError> {
+        let collections_file = config.collections_file.as_ref().map(PathBuf::from);
+        let collections = match collections_file {
+            Some(ref path) if path.exists() => {
+                let contents =
+                    fs::read_to_string(path).map_err(CollectionsError::CollectionsFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| CollectionsError::InvalidCollectionsFile(e.to_string()))?
+            }
+            _ => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            collections: RwLock::new(collections),
+            collections_file,
+        })
+    }
+
+    pub fn all(&self) -> Vec<CollectionReturn> {
+        self.collections
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(slug, details)| CollectionReturn {
+                slug: slug.clone(),
+                details: details.clone(),
+            })
+            .collect()
+    }
+
+    pub fn get(&self, slug: &str) -> Option<CollectionDetails> {
+        self.collections.read().unwrap().get(slug).cloned()
+    }
+
+    ///
+    /// Creates or replaces a collection. Changes are saved before they are applied, so a failed
+    /// write leaves the current collections in place
+    pub fn upsert(&self, slug: &str, details: CollectionDetails) -> Result<(), CollectionsError> {
+        let valid = !slug.is_empty()
+            && slug
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            return Err(CollectionsError::InvalidSlug);
+        }
+        if details.title.trim().is_empty() {
+            return Err(CollectionsError::EmptyTitle);
+        }
+
+        let mut collections = self.collections.write().unwrap();
+        let mut updated = collections.clone();
+        updated.insert(slug.to_owned(), details);
+        self.save(&updated)?;
+        *collections = updated;
+
+        Ok(())
+    }
+
+    ///
+    /// Deletes a collection. Returns false if there is no collection with the slug
+    pub fn remove(&self, slug: &str) -> Result<bool, CollectionsError> {
+        let mut collections = self.collections.write().unwrap();
+        let mut updated = collections.clone();
+        if updated.remove(slug).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&updated)?;
+        *collections = updated;
+        Ok(true)
+    }
+
+    fn save(
+        &self,
+        collections: &BTreeMap<String, CollectionDetails>,
+    ) -> Result<(), CollectionsError> {
+        let path = match self.collections_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        write_json_atomically(path, collections).map_err(CollectionsError::CollectionsFile)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+use pulldown_cmark::{html, Options, Parser};
+
+///
+/// Renders user-written markdown to HTML that is safe to insert into a page. Raw HTML in the
+/// input is allowed through pulldown-cmark and then cleaned by ammonia, which removes scripts,
+/// event handlers, and anything else outside its allowlist. Task list checkboxes are allowed
+/// through, but only ever as disabled checkboxes
+pub fn render_markdown(markdown: &str) -> String {
+    let parser = Parser::new_ext(
+        markdown,
+        Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES | Options::ENABLE_TASKLISTS,
+    );
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, parser);
+
+    ammonia::Builder::default()
+        .add_tags(&["input"])
+        .add_tag_attributes("input", &["checked"])
+        .set_tag_attribute_value("input", "type", "checkbox")
+        .set_tag_attribute_value("input", "disabled", "")
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(&unsafe_html)
+        .to_string()
+}
@@ -0,0 +1,199 @@
+use rocket::{
+    http::Status,
+    response::Responder,
+    tokio::{
+        self,
+        time::{interval, Duration},
+    },
+    Request, Response,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+
+use crate::{config::Config, services::write_json_atomically};
+
+#[derive(Error, Debug)]
+pub enum DisposableEmailError {
+    #[error("Unable to read or write disposable email overrides file: {0}")]
+    OverridesFile(std::io::Error),
+    #[error("Disposable email overrides file is not valid: {0}")]
+    InvalidOverridesFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for DisposableEmailError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        println!("{self}");
+        Response::build().status(Status::InternalServerError).ok()
+    }
+}
+
+///
+/// Domains an admin has blocked or unblocked on top of the fetched list
+#[derive(Default, Clone, Serialize, Deserialize)]
+struct DomainOverrides {
+    added: BTreeSet<String>,
+    removed: BTreeSet<String>,
+}
+
+#[derive(Default)]
+struct BlocklistState {
+    fetched: HashSet<String>,
+    overrides: DomainOverrides,
+}
+
+///
+/// Disposable email domains rejected at registration. The list is fetched as plain text (one
+/// domain per line, `#` comments allowed) from `DISPOSABLE_EMAIL_SOURCE_URL` and refreshed every
+/// `DISPOSABLE_EMAIL_REFRESH_HOURS` (0 fetches it once). Admin additions and removals are layered
+/// on top of it and saved to `DISPOSABLE_EMAIL_OVERRIDES_FILE` when that is set
+pub struct DisposableEmailBlocklist {
+    source_url: Option<String>,
+    refresh_every: Duration,
+    client: reqwest::Client,
+    state: RwLock<BlocklistState>,
+    overrides_file: Option<PathBuf>,
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain.trim().trim_start_matches('@').to_lowercase()
+}
+
+impl DisposableEmailBlocklist {
+    pub fn from_config(config: &Config) -> Result<Self, DisposableEmailError> {
+        let overrides_file = config
+            .disposable_email_overrides_file
+            .as_ref()
+            .map(PathBuf::from);
+        let overrides = match overrides_file {
+            Some(ref path) if path.exists() => {
+                let contents =
+                    fs::read_to_string(path).map_err(DisposableEmailError::OverridesFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| DisposableEmailError::InvalidOverridesFile(e.to_string()))?
+            }
+            _ => DomainOverrides::default(),
+        };
+
+        Ok(Self {
+            source_url: config.disposable_email_source_url.clone(),
+            refresh_every: Duration::from_secs(config.disposable_email_refresh_hours * 60 * 60),
+            client: reqwest::Client::new(),
+            state: RwLock::new(BlocklistState {
+                fetched: HashSet::new(),
+                overrides,
+            }),
+            overrides_file,
+        })
+    }
+
+    pub fn is_blocked(&self, email: &str) -> bool {
+        let domain = match email.rsplit_once('@') {
+            Some((_, domain)) => normalize_domain(domain),
+            None => return false,
+        };
+        let state = self.state.read().unwrap();
+
+        !state.overrides.removed.contains(&domain)
+            && (state.overrides.added.contains(&domain) || state.fetched.contains(&domain))
+    }
+
+    pub fn added_domains(&self) -> Vec<String> {
+        self.state
+            .read()
+            .unwrap()
+            .overrides
+            .added
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    pub fn add_domain(&self, domain: &str) -> Result<(), DisposableEmailError> {
+        let domain = normalize_domain(domain);
+        self.update_overrides(|overrides| {
+            overrides.removed.remove(&domain);
+            overrides.added.insert(domain);
+        })
+    }
+
+    pub fn remove_domain(&self, domain: &str) -> Result<(), DisposableEmailError> {
+        let domain = normalize_domain(domain);
+        self.update_overrides(|overrides| {
+            overrides.added.remove(&domain);
+            overrides.removed.insert(domain);
+        })
+    }
+
+    ///
+    /// Applies `change` to the admin overrides, saving them first so a failed write leaves the
+    /// current overrides in place
+    fn update_overrides<F>(&self, change: F) -> Result<(), DisposableEmailError>
+    where
+        F: FnOnce(&mut DomainOverrides),
+    {
+        let mut state = self.state.write().unwrap();
+        let mut overrides = state.overrides.clone();
+        change(&mut overrides);
+
+        if let Some(ref path) = self.overrides_file {
+            write_json_atomically(path, &overrides).map_err(DisposableEmailError::OverridesFile)?;
+        }
+        state.overrides = overrides;
+
+        Ok(())
+    }
+
+    async fn refresh(&self) -> Result<usize, reqwest::Error> {
+        let source_url = match self.source_url {
+            Some(ref url) => url,
+            None => return Ok(0),
+        };
+
+        let body = self.client.get(source_url).send().await?.text().await?;
+        let fetched: HashSet<String> = body
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(normalize_domain)
+            .collect();
+        let count = fetched.len();
+
+        self.state.write().unwrap().fetched = fetched;
+        Ok(count)
+    }
+
+    async fn refresh_and_log(&self) {
+        match self.refresh().await {
+            Ok(count) => println!("Refreshed disposable email blocklist ({count} domains)"),
+            Err(e) => println!("Unable to refresh disposable email blocklist: {e:?}"),
+        }
+    }
+
+    ///
+    /// Periodically re-fetches the source list. A failed refresh keeps the previous list. With a
+    /// refresh interval of 0 the list is only fetched once at startup
+    pub fn spawn_refresh_job(self: Arc<Self>) {
+        if self.source_url.is_none() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            if self.refresh_every.is_zero() {
+                self.refresh_and_log().await;
+                return;
+            }
+
+            let mut ticker = interval(self.refresh_every);
+            loop {
+                ticker.tick().await;
+                self.refresh_and_log().await;
+            }
+        });
+    }
+}
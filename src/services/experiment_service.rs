@@ -0,0 +1,231 @@
+use chrono::Utc;
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    sync::{Mutex, RwLock},
+};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    models::experiment::{Experiment, ExperimentAssignment},
+    services::write_json_atomically,
+};
+
+/// Log target exposures are written to when `EXPERIMENT_EXPOSURE_LOG_FILE` is not set
+const EXPOSURE_LOG_TARGET: &'static str = "tekxchange::experiment_exposure";
+
+#[derive(Error, Debug)]
+pub enum ExperimentServiceError {
+    #[error("Invalid experiment {0}: {1}")]
+    InvalidExperiment(String, &'static str),
+    #[error("Unable to read or write experiments file: {0}")]
+    ExperimentsFile(std::io::Error),
+    #[error("Experiments file is not valid: {0}")]
+    InvalidExperimentsFile(String),
+    #[error("Unable to open experiment exposure log: {0}")]
+    ExposureLog(std::io::Error),
+}
+
+impl<'r> Responder<'r, 'static> for ExperimentServiceError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidExperiment(..) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+///
+/// A/B experiment definitions and user assignment. Users are bucketed by hashing the experiment
+/// key with their id, so a user keeps the same variant for as long as the experiment's variants
+/// are unchanged. Definitions are saved to `EXPERIMENTS_FILE` when that is set.
+///
+/// Exposures are appended as JSON lines to `EXPERIMENT_EXPOSURE_LOG_FILE` for analysis, or sent to
+/// the `tekxchange::experiment_exposure` log target when no file is set
+pub struct ExperimentService {
+    experiments: RwLock<Vec<Experiment>>,
+    experiments_file: Option<PathBuf>,
+    exposure_log: Option<Mutex<File>>,
+}
+
+fn validate(experiments: &[Experiment]) -> Result<(), ExperimentServiceError> {
+    let mut keys = HashSet::new();
+    for experiment in experiments {
+        let invalid =
+            |reason| ExperimentServiceError::InvalidExperiment(experiment.key.clone(), reason);
+        if !keys.insert(experiment.key.as_str()) {
+            return Err(invalid("duplicate key"));
+        }
+        if experiment
+            .variants
+            .iter()
+            .map(|v| v.weight as u64)
+            .sum::<u64>()
+            == 0
+        {
+            return Err(invalid("variant weights must add up to more than zero"));
+        }
+        let mut names = HashSet::new();
+        if !experiment
+            .variants
+            .iter()
+            .all(|v| names.insert(v.name.as_str()))
+        {
+            return Err(invalid("duplicate variant name"));
+        }
+    }
+
+    Ok(())
+}
+
+fn bucket(experiment: &Experiment, user_id: i64) -> &str {
+    let total: u64 = experiment.variants.iter().map(|v| v.weight as u64).sum();
+    let mut hasher = Sha256::new();
+    hasher.update(experiment.key.as_bytes());
+    hasher.update(b":");
+    hasher.update(user_id.to_be_bytes());
+    let digest = hasher.finalize();
+    let mut point = u64::from_be_bytes(digest[..8].try_into().unwrap()) % total;
+
+    for variant in &experiment.variants {
+        let weight = variant.weight as u64;
+        if point < weight {
+            return &variant.name;
+        }
+        point -= weight;
+    }
+    unreachable!("bucket point is always below the total weight")
+}
+
+impl ExperimentService {
+    pub fn from_config(config: &Config) -> Result<Self, ExperimentServiceError> {
+        let experiments_file = config.experiments_file.as_ref().map(PathBuf::from);
+        let experiments = match experiments_file {
+            Some(ref path) if path.exists() => {
+                let contents =
+                    fs::read_to_string(path).map_err(ExperimentServiceError::ExperimentsFile)?;
+                let experiments: Vec<Experiment> = serde_json::from_str(&contents)
+                    .map_err(|e| ExperimentServiceError::InvalidExperimentsFile(e.to_string()))?;
+                validate(&experiments)
+                    .map_err(|e| ExperimentServiceError::InvalidExperimentsFile(e.to_string()))?;
+                experiments
+            }
+            _ => Vec::new(),
+        };
+
+        let exposure_log = match config.experiment_exposure_log_file {
+            Some(ref path) => Some(Mutex::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .map_err(ExperimentServiceError::ExposureLog)?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            experiments: RwLock::new(experiments),
+            experiments_file,
+            exposure_log,
+        })
+    }
+
+    fn enrolled(experiment: &Experiment, in_segment: &impl Fn(&str) -> bool) -> bool {
+        experiment.active && experiment.segment.as_deref().is_none_or(in_segment)
+    }
+
+    pub fn all(&self) -> Vec<Experiment> {
+        self.experiments.read().unwrap().clone()
+    }
+
+    ///
+    /// The user's variant in every active experiment they are enrolled in. `in_segment` tells
+    /// whether the user belongs to a named segment
+    pub fn assignments(
+        &self,
+        user_id: i64,
+        in_segment: impl Fn(&str) -> bool,
+    ) -> Vec<ExperimentAssignment> {
+        self.experiments
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|experiment| Self::enrolled(experiment, &in_segment))
+            .map(|experiment| ExperimentAssignment {
+                experiment: experiment.key.clone(),
+                variant: bucket(experiment, user_id).to_owned(),
+            })
+            .collect()
+    }
+
+    ///
+    /// Logs that the user was shown their variant of an experiment. Returns the assignment, or
+    /// None if there is no active experiment with the key that the user is enrolled in
+    pub fn record_exposure(
+        &self,
+        user_id: i64,
+        key: &str,
+        in_segment: impl Fn(&str) -> bool,
+    ) -> Option<ExperimentAssignment> {
+        let experiments = self.experiments.read().unwrap();
+        let experiment = experiments
+            .iter()
+            .find(|experiment| experiment.key == key && Self::enrolled(experiment, &in_segment))?;
+        let variant = bucket(experiment, user_id).to_owned();
+        self.log_exposure(user_id, key, &variant);
+
+        Some(ExperimentAssignment {
+            experiment: key.to_owned(),
+            variant,
+        })
+    }
+
+    fn log_exposure(&self, user_id: i64, key: &str, variant: &str) {
+        let exposure = json!({
+            "at": Utc::now().to_rfc3339(),
+            "experiment": key,
+            "variant": variant,
+            "user": user_id,
+        });
+
+        match self.exposure_log {
+            Some(ref file) => {
+                if let Err(e) = writeln!(file.lock().unwrap(), "{exposure}") {
+                    println!("Unable to write experiment exposure: {e}");
+                }
+            }
+            None => log::info!(target: EXPOSURE_LOG_TARGET, "{exposure}"),
+        }
+    }
+
+    ///
+    /// Replaces all experiment definitions, saving them first so a failed write leaves the current
+    /// definitions in place
+    pub fn replace(&self, experiments: Vec<Experiment>) -> Result<usize, ExperimentServiceError> {
+        validate(&experiments)?;
+
+        if let Some(ref path) = self.experiments_file {
+            write_json_atomically(path, &experiments)
+                .map_err(ExperimentServiceError::ExperimentsFile)?;
+        }
+
+        let imported = experiments.len();
+        *self.experiments.write().unwrap() = experiments;
+
+        Ok(imported)
+    }
+}
@@ -0,0 +1,50 @@
+use serde::Serialize;
+use std::{
+    fs::{self, OpenOptions, Permissions},
+    io::{self, Write},
+    os::unix::fs::{OpenOptionsExt, PermissionsExt},
+    path::Path,
+};
+
+///
+/// Saves `value` as pretty-printed JSON. It is written to a `.tmp` file next to `path` which is
+/// then renamed over it, so readers never see a partially written file
+pub(crate) fn write_json_atomically<T>(path: &Path, value: &T) -> io::Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    write_atomically(path, value, None)
+}
+
+///
+/// Like [`write_json_atomically`], but the file is only readable and writable by its owner. Used
+/// for files holding secrets
+pub(crate) fn write_private_json_atomically<T>(path: &Path, value: &T) -> io::Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    write_atomically(path, value, Some(0o600))
+}
+
+fn write_atomically<T>(path: &Path, value: &T, mode: Option<u32>) -> io::Result<()>
+where
+    T: Serialize + ?Sized,
+{
+    let contents = serde_json::to_string_pretty(value)?;
+    let tmp_path = path.with_extension("tmp");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    if let Some(mode) = mode {
+        options.mode(mode);
+    }
+    let mut file = options.open(&tmp_path)?;
+    // `mode` only applies when the file is created, so tighten a leftover `.tmp` file as well
+    if let Some(mode) = mode {
+        file.set_permissions(Permissions::from_mode(mode))?;
+    }
+    file.write_all(contents.as_bytes())?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)
+}
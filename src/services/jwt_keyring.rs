@@ -0,0 +1,231 @@
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::{distributions::Alphanumeric, Rng, RngCore};
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::Sha512;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use thiserror::Error;
+
+use crate::{config::Config, services::write_private_json_atomically};
+
+/// Key id given to the `SECRET` key. Tokens signed before key ids were introduced have no `kid`
+/// header and are verified with this key
+pub const DEFAULT_KEY_ID: &'static str = "default";
+const GENERATED_KEY_LENGTH: usize = 64;
+
+#[derive(Error, Debug)]
+pub enum JwtKeyringError {
+    #[error("Unable to read or write JWT keys file: {0}")]
+    Io(std::io::Error),
+    #[error("JWT keys file is not a valid JWKS document: {0}")]
+    InvalidJwks(String),
+    #[error("JWT_KEYS_FILE must be set to rotate keys, or the new key is lost on restart")]
+    NoKeysFile,
+}
+
+impl<'r> Responder<'r, 'static> for JwtKeyringError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::NoKeysFile => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::Conflict)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct JwkSet {
+    keys: Vec<Jwk>,
+}
+
+///
+/// Symmetric (`oct`) JSON web key. `retired_at` is a non-standard field recording when a key
+/// stopped being used for signing
+#[derive(Serialize, Deserialize)]
+struct Jwk {
+    kty: String,
+    kid: String,
+    k: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retired_at: Option<i64>,
+}
+
+#[derive(Clone)]
+struct SigningKey {
+    kid: String,
+    secret: Vec<u8>,
+    retired_at: Option<i64>,
+}
+
+impl SigningKey {
+    fn hmac(&self) -> Hmac<Sha512> {
+        Hmac::new_from_slice(&self.secret).unwrap()
+    }
+}
+
+///
+/// The set of HMAC keys tokens may be signed with. New tokens are signed with the newest key;
+/// keys replaced by a rotation keep verifying tokens until every token they signed has expired.
+///
+/// Keys are loaded from the JWKS file at `JWT_KEYS_FILE` when it is set, and rotations are written
+/// back to it. `SECRET` seeds the `default` key only while the file is missing or empty, so once
+/// a rotation has retired or dropped that key it stays retired across restarts
+pub struct JwtKeyring {
+    keys: RwLock<Vec<SigningKey>>,
+    keys_file: Option<PathBuf>,
+    token_ttl_seconds: i64,
+}
+
+impl JwtKeyring {
+    pub fn from_config(config: &Config) -> Result<Self, JwtKeyringError> {
+        let keys_file = config.jwt_keys_file.as_ref().map(PathBuf::from);
+        let mut keys = match keys_file {
+            Some(ref path) if path.exists() => Self::read_keys_file(path)?,
+            _ => Vec::new(),
+        };
+
+        if keys.is_empty() {
+            keys.push(SigningKey {
+                kid: DEFAULT_KEY_ID.to_owned(),
+                secret: config.jwt_secret.as_bytes().to_vec(),
+                retired_at: None,
+            });
+        }
+
+        Ok(Self {
+            keys: RwLock::new(keys),
+            keys_file,
+            token_ttl_seconds: config.jwt_ttl_hours * 60 * 60,
+        })
+    }
+
+    pub fn token_ttl_seconds(&self) -> i64 {
+        self.token_ttl_seconds
+    }
+
+    ///
+    /// Key id and key new tokens should be signed with
+    pub fn signing_key(&self) -> (String, Hmac<Sha512>) {
+        let keys = self.keys.read().unwrap();
+        let newest = keys
+            .iter()
+            .rev()
+            .find(|key| key.retired_at.is_none())
+            .or_else(|| keys.last())
+            .unwrap();
+
+        (newest.kid.clone(), newest.hmac())
+    }
+
+    ///
+    /// Key for verifying a token with the given `kid` header, if that key is still honored
+    pub fn verifying_key(&self, kid: Option<&str>) -> Option<Hmac<Sha512>> {
+        let kid = kid.unwrap_or(DEFAULT_KEY_ID);
+        let now = Utc::now().timestamp();
+
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .find(|key| key.kid == kid)
+            .filter(|key| !self.is_expired(key, now))
+            .map(SigningKey::hmac)
+    }
+
+    ///
+    /// Generates a new signing key, retires the current ones, and drops retired keys that can no
+    /// longer have valid tokens. Returns the new key id. Fails without `JWT_KEYS_FILE`, since
+    /// an unsaved key would invalidate every token signed with it on the next restart
+    pub fn rotate(&self) -> Result<String, JwtKeyringError> {
+        let path = self.keys_file.as_ref().ok_or(JwtKeyringError::NoKeysFile)?;
+        let now = Utc::now().timestamp();
+        let mut secret = vec![0u8; GENERATED_KEY_LENGTH];
+        rand::thread_rng().fill_bytes(&mut secret);
+        let suffix: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+        let kid = format!("{now}-{suffix}");
+
+        let mut keys = self.keys.write().unwrap();
+        let mut rotated = keys.clone();
+        for key in rotated.iter_mut() {
+            key.retired_at.get_or_insert(now);
+        }
+        rotated.retain(|key| !self.is_expired(key, now));
+        rotated.push(SigningKey {
+            kid: kid.clone(),
+            secret,
+            retired_at: None,
+        });
+
+        // Save first so a failed write leaves the current keys in place
+        Self::write_keys_file(path, &rotated)?;
+        *keys = rotated;
+
+        Ok(kid)
+    }
+
+    fn is_expired(&self, key: &SigningKey, now: i64) -> bool {
+        match key.retired_at {
+            Some(retired_at) => retired_at + self.token_ttl_seconds < now,
+            None => false,
+        }
+    }
+
+    fn read_keys_file(path: &Path) -> Result<Vec<SigningKey>, JwtKeyringError> {
+        let contents = fs::read_to_string(path).map_err(JwtKeyringError::Io)?;
+        let jwks: JwkSet = serde_json::from_str(&contents)
+            .map_err(|e| JwtKeyringError::InvalidJwks(e.to_string()))?;
+
+        jwks.keys
+            .into_iter()
+            .map(|jwk| {
+                if jwk.kty != "oct" {
+                    return Err(JwtKeyringError::InvalidJwks(format!(
+                        "key {} has unsupported kty {}",
+                        jwk.kid, jwk.kty
+                    )));
+                }
+                let secret = base64::decode_config(&jwk.k, base64::URL_SAFE_NO_PAD)
+                    .map_err(|e| JwtKeyringError::InvalidJwks(e.to_string()))?;
+
+                Ok(SigningKey {
+                    kid: jwk.kid,
+                    secret,
+                    retired_at: jwk.retired_at,
+                })
+            })
+            .collect()
+    }
+
+    fn write_keys_file(path: &Path, keys: &[SigningKey]) -> Result<(), JwtKeyringError> {
+        let jwks = JwkSet {
+            keys: keys
+                .iter()
+                .map(|key| Jwk {
+                    kty: "oct".to_owned(),
+                    kid: key.kid.clone(),
+                    k: base64::encode_config(&key.secret, base64::URL_SAFE_NO_PAD),
+                    retired_at: key.retired_at,
+                })
+                .collect(),
+        };
+        write_private_json_atomically(path, &jwks).map_err(JwtKeyringError::Io)?;
+
+        Ok(())
+    }
+}
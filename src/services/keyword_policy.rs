@@ -0,0 +1,150 @@
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde_json::json;
+use std::{fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    models::keyword_policy::{KeywordAction, KeywordCheck, KeywordOutcome, KeywordRule},
+    services::write_json_atomically,
+};
+
+#[derive(Error, Debug)]
+pub enum KeywordPolicyError {
+    #[error("The {0} contains a word that is not allowed")]
+    Blocked(&'static str),
+    #[error("Unable to read or write keyword policy file: {0}")]
+    PolicyFile(std::io::Error),
+    #[error("Keyword policy file is not valid: {0}")]
+    InvalidPolicyFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for KeywordPolicyError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::Blocked(_) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+///
+/// Admin-managed keyword rules applied to product titles and usernames. Rules are replaced as a
+/// whole and saved to `KEYWORD_POLICY_FILE` when that is set
+pub struct KeywordPolicy {
+    rules: RwLock<Vec<KeywordRule>>,
+    policy_file: Option<PathBuf>,
+}
+
+///
+/// Byte ranges of whole-word, ASCII case-insensitive occurrences of `keyword` in `text`
+fn find_word(text: &str, keyword: &str) -> Vec<(usize, usize)> {
+    let haystack = text.to_ascii_lowercase();
+    let needle = keyword.trim().to_ascii_lowercase();
+    if needle.is_empty() {
+        return Vec::new();
+    }
+
+    let is_word_char = |c: Option<char>| c.is_some_and(char::is_alphanumeric);
+    haystack
+        .match_indices(&needle)
+        .map(|(start, _)| (start, start + needle.len()))
+        .filter(|(start, end)| {
+            !is_word_char(haystack[..*start].chars().next_back())
+                && !is_word_char(haystack[*end..].chars().next())
+        })
+        .collect()
+}
+
+impl KeywordPolicy {
+    pub fn from_config(config: &Config) -> Result<Self, KeywordPolicyError> {
+        let policy_file = config.keyword_policy_file.as_ref().map(PathBuf::from);
+        let rules = match policy_file {
+            Some(ref path) if path.exists() => {
+                let contents = fs::read_to_string(path).map_err(KeywordPolicyError::PolicyFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| KeywordPolicyError::InvalidPolicyFile(e.to_string()))?
+            }
+            _ => Vec::new(),
+        };
+
+        Ok(Self {
+            rules: RwLock::new(rules),
+            policy_file,
+        })
+    }
+
+    pub fn rules(&self) -> Vec<KeywordRule> {
+        self.rules.read().unwrap().clone()
+    }
+
+    ///
+    /// Replaces every rule, saving them first so a failed write leaves the current rules in place
+    pub fn set_rules(&self, rules: Vec<KeywordRule>) -> Result<(), KeywordPolicyError> {
+        if let Some(ref path) = self.policy_file {
+            write_json_atomically(path, &rules).map_err(KeywordPolicyError::PolicyFile)?;
+        }
+
+        *self.rules.write().unwrap() = rules;
+        Ok(())
+    }
+
+    ///
+    /// How the policy treats `text`, without acting on it
+    pub fn check(&self, text: &str) -> KeywordCheck {
+        let mut check = KeywordCheck {
+            outcome: KeywordOutcome::Allowed,
+            text: text.to_owned(),
+            matched: Vec::new(),
+        };
+
+        for rule in self.rules.read().unwrap().iter() {
+            let found = find_word(&check.text, &rule.keyword);
+            if found.is_empty() {
+                continue;
+            }
+            check.matched.push(rule.keyword.clone());
+
+            match rule.action {
+                KeywordAction::Block => check.outcome = KeywordOutcome::Blocked,
+                KeywordAction::Flag if check.outcome == KeywordOutcome::Allowed => {
+                    check.outcome = KeywordOutcome::Flagged
+                }
+                KeywordAction::Flag => {}
+                KeywordAction::Replace => {
+                    let replacement = rule
+                        .replacement
+                        .clone()
+                        .unwrap_or_else(|| "*".repeat(rule.keyword.trim().chars().count()));
+                    for (start, end) in found.into_iter().rev() {
+                        check.text.replace_range(start..end, &replacement);
+                    }
+                }
+            }
+        }
+
+        check
+    }
+
+    ///
+    /// Applies the policy to a user-supplied `field`, returning the text to store. Flagged text
+    /// is accepted and logged
+    pub fn apply(&self, field: &'static str, text: String) -> Result<String, KeywordPolicyError> {
+        let check = self.check(&text);
+        match check.outcome {
+            KeywordOutcome::Blocked => Err(KeywordPolicyError::Blocked(field)),
+            KeywordOutcome::Flagged => {
+                println!("Flagged {field} {text:?} for keywords {:?}", check.matched);
+                Ok(check.text)
+            }
+            KeywordOutcome::Allowed => Ok(check.text),
+        }
+    }
+}
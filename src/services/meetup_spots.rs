@@ -0,0 +1,143 @@
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde_json::json;
+use std::{fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    models::meetup::{MeetupSpot, MeetupSuggestion},
+    services::write_json_atomically,
+};
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+#[derive(Error, Debug)]
+pub enum MeetupSpotsError {
+    #[error("Meetup spot `{0}` has coordinates outside the valid latitude or longitude range")]
+    InvalidCoordinates(String),
+    #[error("lat must be between -90 and 90 and lng between -180 and 180")]
+    InvalidLocation,
+    #[error("Unable to read or write meetup spots file: {0}")]
+    SpotsFile(std::io::Error),
+    #[error("Meetup spots file is not valid: {0}")]
+    InvalidSpotsFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for MeetupSpotsError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidCoordinates(_) | Self::InvalidLocation => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+///
+/// Safe exchange locations such as police stations and public places. The dataset is replaced as
+/// a whole through the admin API and saved to `MEETUP_SPOTS_FILE` when that is set
+pub struct MeetupSpots {
+    spots: RwLock<Vec<MeetupSpot>>,
+    spots_file: Option<PathBuf>,
+}
+
+///
+/// Whether the point is a real latitude and longitude. Also false for NaN
+fn is_valid_location(latitude: f64, longitude: f64) -> bool {
+    (-90.0..=90.0).contains(&latitude) && (-180.0..=180.0).contains(&longitude)
+}
+
+fn validate_spots(spots: &[MeetupSpot]) -> Result<(), MeetupSpotsError> {
+    match spots
+        .iter()
+        .find(|spot| !is_valid_location(spot.latitude, spot.longitude))
+    {
+        Some(spot) => Err(MeetupSpotsError::InvalidCoordinates(spot.name.clone())),
+        None => Ok(()),
+    }
+}
+
+/// Great-circle distance between two points
+fn haversine_km((lat1, lng1): (f64, f64), (lat2, lng2): (f64, f64)) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let d_lat = lat2 - lat1;
+    let d_lng = (lng2 - lng1).to_radians();
+    let a = (d_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lng / 2.0).sin().powi(2);
+
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+impl MeetupSpots {
+    pub fn from_config(config: &Config) -> Result<Self, MeetupSpotsError> {
+        let spots_file = config.meetup_spots_file.as_ref().map(PathBuf::from);
+        let spots = match spots_file {
+            Some(ref path) if path.exists() => {
+                let contents = fs::read_to_string(path).map_err(MeetupSpotsError::SpotsFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| MeetupSpotsError::InvalidSpotsFile(e.to_string()))?
+            }
+            _ => Vec::new(),
+        };
+        validate_spots(&spots)?;
+
+        Ok(Self {
+            spots: RwLock::new(spots),
+            spots_file,
+        })
+    }
+
+    pub fn all(&self) -> Vec<MeetupSpot> {
+        self.spots.read().unwrap().clone()
+    }
+
+    ///
+    /// Verified spots within `radius_km` of the point, nearest first
+    pub fn nearby(
+        &self,
+        latitude: f64,
+        longitude: f64,
+        radius_km: f64,
+        limit: usize,
+    ) -> Result<Vec<MeetupSuggestion>, MeetupSpotsError> {
+        if !is_valid_location(latitude, longitude) {
+            return Err(MeetupSpotsError::InvalidLocation);
+        }
+
+        let mut suggestions: Vec<MeetupSuggestion> = self
+            .spots
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|spot| spot.verified)
+            .map(|spot| MeetupSuggestion {
+                distance_km: haversine_km((latitude, longitude), (spot.latitude, spot.longitude)),
+                spot: spot.clone(),
+            })
+            .filter(|suggestion| suggestion.distance_km <= radius_km)
+            .collect();
+
+        suggestions.sort_by(|a, b| a.distance_km.total_cmp(&b.distance_km));
+        suggestions.truncate(limit);
+        Ok(suggestions)
+    }
+
+    ///
+    /// Replaces the dataset, saving it first so a failed write leaves the current spots in place
+    pub fn import(&self, spots: Vec<MeetupSpot>) -> Result<usize, MeetupSpotsError> {
+        validate_spots(&spots)?;
+        if let Some(ref path) = self.spots_file {
+            write_json_atomically(path, &spots).map_err(MeetupSpotsError::SpotsFile)?;
+        }
+
+        let imported = spots.len();
+        *self.spots.write().unwrap() = spots;
+
+        Ok(imported)
+    }
+}
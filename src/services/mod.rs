@@ -1,5 +1,37 @@
+mod announcement_board;
+mod backup_service;
+mod captcha_service;
+mod collections;
+mod content_renderer;
+mod disposable_email;
+mod experiment_service;
+mod json_file;
+mod jwt_keyring;
+mod keyword_policy;
+mod meetup_spots;
 mod product_service;
+mod qr_code;
+mod ranking_config;
+mod reserved_usernames;
+mod segments;
+mod spam_guard;
 mod user_service;
 
+pub use announcement_board::{AnnouncementBoard, AnnouncementBoardError};
+pub use backup_service::{BackupService, BackupServiceError, BackupTable};
+pub use captcha_service::{captcha_verifier_from_config, CaptchaError, CaptchaService, FailedLogins};
+pub use collections::{Collections, CollectionsError};
+pub use content_renderer::render_markdown;
+pub use disposable_email::{DisposableEmailBlocklist, DisposableEmailError};
+pub use experiment_service::{ExperimentService, ExperimentServiceError};
+pub(crate) use json_file::{write_json_atomically, write_private_json_atomically};
+pub use jwt_keyring::{JwtKeyring, JwtKeyringError};
+pub use keyword_policy::{KeywordPolicy, KeywordPolicyError};
+pub use meetup_spots::{MeetupSpots, MeetupSpotsError};
 pub use product_service::{ProductService, ProductServiceError};
+pub use qr_code::{QrCodeError, QrFormat, QrImage};
+pub use ranking_config::{RankingConfig, RankingConfigError};
+pub use reserved_usernames::{ReservedUsernames, ReservedUsernamesError};
+pub use segments::{Segments, SegmentsError};
+pub use spam_guard::{SpamGuard, SpamGuardError};
 pub use user_service::{UserService, UserServiceError};
@@ -1,5 +1,6 @@
 use entity::product::{ActiveModel as ProductActiveModel, Entity as ProductEntity};
 use rocket::{
+    futures::{Stream, StreamExt},
     http::Status,
     outcome::Outcome,
     request::{self, FromRequest},
@@ -7,17 +8,25 @@ use rocket::{
     Request, Response,
 };
 use sea_orm::{
-    entity::prelude::*, query::Condition, ActiveModelTrait, ActiveValue, DatabaseConnection,
+    entity::prelude::*, query::Condition, sea_query::Expr, ActiveModelTrait, ActiveValue,
+    ConnectionTrait, DatabaseConnection, DbBackend, QueryOrder, QuerySelect, Statement,
+    TransactionTrait,
 };
 use serde_json::json;
 use thiserror::Error;
 
 use crate::{
-    db::establish_connection,
+    config::Config,
+    db::{establish_connection, DbError},
     models::{
-        product::{ProductDetails, ProductReturn},
+        bulk::BulkItemResult,
+        collection::CollectionDetails,
+        product::{ProductComparison, ProductDetails, ProductPatch, ProductQuality, ProductReturn},
+        public_id::PublicId,
+        ranking::{RankingWeights, ScoreExplanation, ScoreParts},
         user::{AuthUser, MinUserReturnDto},
     },
+    services::{render_markdown, KeywordPolicyError, SpamGuard, SpamGuardError},
 };
 
 #[derive(Error, Debug)]
@@ -26,10 +35,18 @@ pub enum ProductServiceError {
     DbError(crate::db::DbError),
     #[error(transparent)]
     OrmError(sea_orm::DbErr),
-    #[error("Product with id {0} not found")]
+    #[error("Product with id {} not found", PublicId(*.0))]
     NotFound(i64),
     #[error("You are not authorized to perform changes on this product")]
     NotAllowed,
+    #[error("{0} cannot be null")]
+    NullField(&'static str),
+    #[error("At most {0} products can be compared at once")]
+    TooManyCompared(usize),
+    #[error(transparent)]
+    SpamError(SpamGuardError),
+    #[error(transparent)]
+    PolicyError(KeywordPolicyError),
     #[error("An unknown error occurred")]
     Unknown,
 }
@@ -45,40 +62,77 @@ impl<'r> Responder<'r, 'static> for ProductServiceError {
                     .status(Status::NotFound)
                     .ok()
             }
+            Self::NullField(_) | Self::TooManyCompared(_) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
             Self::NotAllowed => {
                 Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
                     .status(Status::Forbidden)
                     .ok()
             }
+            Self::SpamError(SpamGuardError::ListingLimitReached(_)) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::TooManyRequests)
+                    .ok()
+            }
+            Self::SpamError(SpamGuardError::OrmError(_)) => {
+                Response::build().status(Status::InternalServerError).ok()
+            }
+            Self::PolicyError(e) => e.respond_to(request),
         }
     }
 }
 
 pub struct ProductService {
     db_connection: DatabaseConnection,
+    spam_guard: SpamGuard,
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for ProductService {
     type Error = ProductServiceError;
 
-    async fn from_request(_: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        match establish_connection()
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.rocket().state::<Config>() {
+            Some(config) => config,
+            None => {
+                return Outcome::Failure((
+                    Status::InternalServerError,
+                    ProductServiceError::DbError(DbError::ConfigError),
+                ))
+            }
+        };
+
+        match establish_connection(config)
             .await
             .map_err(|e| ProductServiceError::DbError(e))
         {
             Err(e) => return Outcome::Failure((Status::InternalServerError, e)),
-            Ok(db) => return Outcome::Success(Self { db_connection: db }),
+            Ok(db) => return Outcome::Success(Self::new(db, config)),
         }
     }
 }
 
 impl ProductService {
+    pub fn new(db_connection: DatabaseConnection, config: &Config) -> Self {
+        Self {
+            db_connection,
+            spam_guard: SpamGuard::from_config(config),
+        }
+    }
+
     pub async fn create_new_product(
         &mut self,
         create: ProductDetails,
         creating_user: AuthUser,
     ) -> Result<i64, ProductServiceError> {
+        self.spam_guard
+            .check_new_listing(&self.db_connection, &creating_user.user)
+            .await
+            .map_err(ProductServiceError::SpamError)?;
+
         let to_create = ProductActiveModel {
             price: ActiveValue::Set(create.price),
             description: ActiveValue::Set(create.description),
@@ -114,24 +168,258 @@ impl ProductService {
             .map_err(|e| ProductServiceError::OrmError(e))?;
 
         if let Some((prod, user)) = found {
-            if let None = user {
-                return Err(ProductServiceError::Unknown);
-            }
-            let user = user.unwrap();
-            return Ok(ProductReturn {
-                title: prod.product_title,
-                description: prod.description,
-                price: f64::try_from(prod.price).map_err(|_| ProductServiceError::Unknown)?,
-                created_by: MinUserReturnDto {
-                    id: user.id,
-                    username: user.username,
-                },
-            });
+            return Self::to_product_return(prod, user);
         } else {
             return Err(ProductServiceError::NotFound(id));
         }
     }
 
+    ///
+    /// Recency score from 1 for a brand new listing towards 0, halving every `half_life_param`
+    /// days
+    fn recency_sql(half_life_param: &str) -> String {
+        let created_at = format!("\"{}\".\"created_at\"", ProductEntity.table_name());
+        format!(
+            "(1.0 / (1.0 + EXTRACT(EPOCH FROM (NOW() - {created_at})) / 86400.0 / {half_life_param}))::float8"
+        )
+    }
+
+    ///
+    /// Typo-tolerant search over product titles using pg_trgm word similarity. Results are ranked
+    /// by a weighted blend of title similarity and listing recency, so close matches on fresh
+    /// listings come first
+    pub async fn search_products(
+        &mut self,
+        query: &str,
+        limit: u64,
+        weights: RankingWeights,
+    ) -> Result<Vec<ProductReturn>, ProductServiceError> {
+        let score = Expr::cust_with_values(
+            &format!(
+                "word_similarity($1, product_title) * $3 + {} * $4",
+                Self::recency_sql("$2")
+            ),
+            vec![
+                Value::from(query),
+                Value::from(weights.recency_half_life_days),
+                Value::from(weights.similarity_weight),
+                Value::from(weights.recency_weight),
+            ],
+        );
+
+        // `<%` can use the trigram index on product titles, but takes its threshold from a
+        // setting rather than a parameter, so it is set for this transaction only
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+        txn.execute(Statement::from_string(
+            DbBackend::Postgres,
+            format!(
+                "SET LOCAL pg_trgm.word_similarity_threshold = {}",
+                weights.similarity_threshold
+            ),
+        ))
+        .await
+        .map_err(ProductServiceError::OrmError)?;
+
+        let found = ProductEntity::find()
+            .find_also_related(entity::user::Entity)
+            .filter(Expr::cust_with_values("$1 <% product_title", vec![Value::from(query)]))
+            .order_by_desc(score)
+            .limit(limit)
+            .all(&txn)
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+        txn.commit()
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        found
+            .into_iter()
+            .map(|(prod, user)| Self::to_product_return(prod, user))
+            .collect()
+    }
+
+    ///
+    /// Breaks down the search score a product would get for a query under the given weights
+    pub async fn explain_search_score(
+        &mut self,
+        id: i64,
+        query: &str,
+        weights: RankingWeights,
+    ) -> Result<ScoreExplanation, ProductServiceError> {
+        let parts = ProductEntity::find_by_id(id)
+            .select_only()
+            .column_as(
+                Expr::cust_with_values(
+                    "word_similarity($1, product_title)::float8",
+                    vec![Value::from(query)],
+                ),
+                "similarity",
+            )
+            .column_as(
+                Expr::cust_with_values(
+                    &Self::recency_sql("$1"),
+                    vec![Value::from(weights.recency_half_life_days)],
+                ),
+                "recency",
+            )
+            .into_model::<ScoreParts>()
+            .one(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?
+            .ok_or(ProductServiceError::NotFound(id))?;
+
+        Ok(ScoreExplanation::new(parts, weights))
+    }
+
+    ///
+    /// Every product with its seller, in id order, read from the database as the stream is polled
+    /// rather than loaded up front
+    pub async fn stream_products(
+        &self,
+    ) -> Result<impl Stream<Item = Result<ProductReturn, ProductServiceError>> + '_, ProductServiceError>
+    {
+        let found = ProductEntity::find()
+            .find_also_related(entity::user::Entity)
+            .order_by_asc(entity::product::Column::Id)
+            .stream(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        Ok(found.map(|row| {
+            row.map_err(ProductServiceError::OrmError)
+                .and_then(|(prod, user)| Self::to_product_return(prod, user))
+        }))
+    }
+
+    ///
+    /// Newest products in a collection: its curated products plus those matching its rule
+    pub async fn get_collection_products(
+        &mut self,
+        collection: &CollectionDetails,
+        limit: u64,
+    ) -> Result<Vec<ProductReturn>, ProductServiceError> {
+        use entity::product;
+
+        let mut included = Condition::any();
+        if !collection.product_ids.is_empty() {
+            let ids: Vec<i64> = collection.product_ids.iter().map(|id| id.0).collect();
+            included = included.add(product::Column::Id.is_in(ids));
+        }
+        if let Some(ref rule) = collection.rule {
+            let mut matches = Condition::all();
+            if let Some(min_price) = rule.min_price {
+                matches = matches.add(product::Column::Price.gte(min_price));
+            }
+            if let Some(max_price) = rule.max_price {
+                matches = matches.add(product::Column::Price.lte(max_price));
+            }
+            if let Some(ref title) = rule.title_contains {
+                matches = matches.add(Expr::cust_with_values(
+                    "product_title ILIKE '%' || $1 || '%'",
+                    vec![Value::from(escape_like(title))],
+                ));
+            }
+            included = included.add(matches);
+        }
+        if included.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let found = ProductEntity::find()
+            .find_also_related(entity::user::Entity)
+            .filter(included)
+            .order_by_desc(product::Column::CreatedAt)
+            .limit(limit)
+            .all(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        found
+            .into_iter()
+            .map(|(prod, user)| Self::to_product_return(prod, user))
+            .collect()
+    }
+
+    fn to_product_return(
+        prod: entity::product::Model,
+        user: Option<entity::user::Model>,
+    ) -> Result<ProductReturn, ProductServiceError> {
+        let user = user.ok_or(ProductServiceError::Unknown)?;
+        Ok(ProductReturn {
+            id: prod.id.into(),
+            title: prod.product_title,
+            description_html: render_markdown(&prod.description),
+            description: prod.description,
+            price: f64::try_from(prod.price).map_err(|_| ProductServiceError::Unknown)?,
+            created_by: MinUserReturnDto {
+                id: user.id.into(),
+                username: user.username,
+            },
+        })
+    }
+
+    ///
+    /// Attribute matrix for the given products, in the order their ids were given. Repeated ids
+    /// are compared once
+    pub async fn compare_products(
+        &mut self,
+        ids: &[i64],
+        max_compared: usize,
+    ) -> Result<ProductComparison, ProductServiceError> {
+        let mut unique_ids: Vec<i64> = Vec::with_capacity(ids.len());
+        for id in ids {
+            if !unique_ids.contains(id) {
+                unique_ids.push(*id);
+            }
+        }
+        if unique_ids.len() > max_compared {
+            return Err(ProductServiceError::TooManyCompared(max_compared));
+        }
+
+        let found = ProductEntity::find()
+            .filter(entity::product::Column::Id.is_in(unique_ids.clone()))
+            .all(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        let products = unique_ids
+            .into_iter()
+            .map(|id| {
+                found
+                    .iter()
+                    .find(|product| product.id == id)
+                    .cloned()
+                    .ok_or(ProductServiceError::NotFound(id))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ProductComparison::from(products.as_slice()))
+    }
+
+    ///
+    /// Completeness score and improvement hints for a listing, only visible to its seller
+    pub async fn get_product_quality(
+        &mut self,
+        id: i64,
+        user: AuthUser,
+    ) -> Result<ProductQuality, ProductServiceError> {
+        let product = ProductEntity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?
+            .ok_or(ProductServiceError::NotFound(id))?;
+
+        if product.created_by != user.user.id {
+            return Err(ProductServiceError::NotAllowed);
+        }
+
+        Ok(ProductQuality::from(&product))
+    }
+
     pub async fn update_product_by_id(
         &mut self,
         id: i64,
@@ -139,7 +427,7 @@ impl ProductService {
         user: AuthUser,
     ) -> Result<(), ProductServiceError> {
         let db_product = self.get_product_by_id(id).await?;
-        if db_product.created_by.id != user.user.id {
+        if db_product.created_by.id != PublicId(user.user.id) {
             return Err(ProductServiceError::NotAllowed);
         }
 
@@ -172,6 +460,105 @@ impl ProductService {
         Ok(())
     }
 
+    ///
+    /// Updates only the fields present in `patch`, leaving the rest of the product unchanged
+    pub async fn patch_product_by_id(
+        &mut self,
+        id: i64,
+        patch: ProductPatch,
+        user: AuthUser,
+    ) -> Result<(), ProductServiceError> {
+        let found = ProductEntity::find_by_id(id)
+            .one(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?
+            .ok_or(ProductServiceError::NotFound(id))?;
+        if found.created_by != user.user.id {
+            return Err(ProductServiceError::NotAllowed);
+        }
+
+        let mut active_product: ProductActiveModel = found.into();
+        let null_field = |field| move |_| ProductServiceError::NullField(field);
+        patch
+            .description
+            .apply_required(&mut active_product.description)
+            .map_err(null_field("description"))?;
+        patch
+            .title
+            .apply_required(&mut active_product.product_title)
+            .map_err(null_field("title"))?;
+        patch
+            .price
+            .apply_required(&mut active_product.price)
+            .map_err(null_field("price"))?;
+        patch
+            .country
+            .apply_required(&mut active_product.location_country)
+            .map_err(null_field("country"))?;
+        patch
+            .state
+            .apply_required(&mut active_product.location_state)
+            .map_err(null_field("state"))?;
+        patch
+            .city
+            .apply_required(&mut active_product.location_city)
+            .map_err(null_field("city"))?;
+        patch
+            .zip
+            .apply_required(&mut active_product.location_zip)
+            .map_err(null_field("zip"))?;
+        patch.latitude.apply(&mut active_product.location_latitude);
+        patch.longitude.apply(&mut active_product.location_longitude);
+
+        if !active_product.is_changed() {
+            return Ok(());
+        }
+
+        active_product
+            .update(&self.db_connection)
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        Ok(())
+    }
+
+    ///
+    /// Deletes each of the products in a single transaction, reporting ids that did not exist.
+    /// Database errors abort and roll back the whole batch
+    pub async fn bulk_delete_products(
+        &mut self,
+        ids: &[i64],
+    ) -> Result<Vec<BulkItemResult>, ProductServiceError> {
+        use entity::product;
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        let mut results = Vec::with_capacity(ids.len());
+        for &id in ids {
+            let deleted = ProductEntity::delete_many()
+                .filter(product::Column::Id.eq(id))
+                .exec(&txn)
+                .await
+                .map_err(ProductServiceError::OrmError)?;
+
+            results.push(BulkItemResult {
+                id: id.into(),
+                ok: deleted.rows_affected > 0,
+                error: (deleted.rows_affected == 0)
+                    .then(|| format!("{}", ProductServiceError::NotFound(id))),
+            });
+        }
+
+        txn.commit()
+            .await
+            .map_err(ProductServiceError::OrmError)?;
+
+        Ok(results)
+    }
+
     pub async fn delete_product_by_id(
         &mut self,
         id: i64,
@@ -196,3 +583,119 @@ impl ProductService {
         Ok(())
     }
 }
+
+///
+/// Escapes `%`, `_`, and the escape character itself so `value` only matches literally inside a
+/// `LIKE` pattern
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use sea_orm::{prelude::Decimal, DatabaseBackend, MockDatabase, MockExecResult, Transaction};
+
+    use super::{escape_like, ProductService};
+    use crate::{
+        config::Config,
+        models::{collection::CollectionDetails, public_id::PublicId, ranking::RankingWeights},
+    };
+
+    fn product(id: i64) -> entity::product::Model {
+        let now = Utc::now().naive_utc();
+        entity::product::Model {
+            id,
+            created_at: now,
+            updated_at: now,
+            product_title: format!("Product {id}"),
+            description: "Description".to_owned(),
+            price: Decimal::new(100, 0),
+            created_by: 1,
+            location_country: "US".to_owned(),
+            location_state: "TX".to_owned(),
+            location_city: "Austin".to_owned(),
+            location_zip: "78701".to_owned(),
+            location_latitude: None,
+            location_longitude: None,
+        }
+    }
+
+    fn seller() -> entity::user::Model {
+        let now = Utc::now().naive_utc();
+        entity::user::Model {
+            id: 1,
+            created_at: now,
+            updated_at: now,
+            username: "seller".to_owned(),
+            email: "seller@example.com".to_owned(),
+            password: String::new(),
+            role: 1,
+        }
+    }
+
+    fn listing_rows() -> Vec<(entity::product::Model, entity::user::Model)> {
+        (1..=3).map(|id| (product(id), seller())).collect()
+    }
+
+    ///
+    /// Statements run, counting each statement of a transaction. `Transaction` doesn't expose its
+    /// statements, so they are counted from its debug output
+    fn statement_count(log: &[Transaction]) -> usize {
+        log.iter()
+            .map(|transaction| format!("{transaction:?}").matches("Statement {").count())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn collection_listing_loads_sellers_in_one_query() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_query_results(vec![listing_rows()])
+            .into_connection();
+        let mut product_service = ProductService::new(db, &Config::for_tests());
+        let collection = CollectionDetails {
+            title: "Deals".to_owned(),
+            product_ids: (1..=3).map(PublicId).collect(),
+            rule: None,
+        };
+
+        let products = product_service
+            .get_collection_products(&collection, 20)
+            .await
+            .unwrap();
+
+        assert_eq!(products.len(), 3);
+        let log = product_service.db_connection.into_transaction_log();
+        assert_eq!(statement_count(&log), 1);
+    }
+
+    #[tokio::test]
+    async fn search_loads_sellers_in_one_query() {
+        let db = MockDatabase::new(DatabaseBackend::Postgres)
+            .append_exec_results(vec![MockExecResult {
+                last_insert_id: 0,
+                rows_affected: 0,
+            }])
+            .append_query_results(vec![listing_rows()])
+            .into_connection();
+        let mut product_service = ProductService::new(db, &Config::for_tests());
+
+        let products = product_service
+            .search_products("product", 20, RankingWeights::default())
+            .await
+            .unwrap();
+
+        assert_eq!(products.len(), 3);
+        // BEGIN, SET LOCAL for the similarity threshold, the search itself, and COMMIT
+        let log = product_service.db_connection.into_transaction_log();
+        assert_eq!(statement_count(&log), 4);
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like("50%_off\\"), "50\\%\\_off\\\\");
+    }
+}
@@ -0,0 +1,119 @@
+use image::{DynamicImage, ImageOutputFormat, Luma};
+use qrcode::{render::svg, QrCode};
+use rocket::{
+    http::{ContentType, Header, Status},
+    response::Responder,
+    Request, Response,
+};
+use sha2::{Digest, Sha256};
+use std::{fmt::Write, io::Cursor};
+use thiserror::Error;
+
+/// The encoded URL depends on `FRONTEND_URL` and the product can be deleted, so clients only
+/// cache briefly and then revalidate with the ETag
+const CACHE_MAX_AGE_SECONDS: u64 = 5 * 60;
+const MIN_DIMENSION: u32 = 256;
+
+#[derive(Error, Debug)]
+pub enum QrCodeError {
+    #[error("Unable to encode QR code: {0}")]
+    Encode(qrcode::types::QrError),
+    #[error("Unable to render QR code: {0}")]
+    Render(image::ImageError),
+}
+
+impl<'r> Responder<'r, 'static> for QrCodeError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        println!("{self}");
+        Response::build().status(Status::InternalServerError).ok()
+    }
+}
+
+#[derive(FromFormField, Debug, Clone, Copy, Default)]
+pub enum QrFormat {
+    #[default]
+    Svg,
+    Png,
+}
+
+///
+/// A rendered QR code, served with a short `max-age` and an ETag derived from the image
+pub struct QrImage {
+    content_type: ContentType,
+    body: Vec<u8>,
+}
+
+impl QrImage {
+    pub fn render(data: &str, format: QrFormat) -> Result<Self, QrCodeError> {
+        let code = QrCode::new(data.as_bytes()).map_err(QrCodeError::Encode)?;
+
+        match format {
+            QrFormat::Svg => Ok(Self {
+                content_type: ContentType::SVG,
+                body: code
+                    .render::<svg::Color>()
+                    .min_dimensions(MIN_DIMENSION, MIN_DIMENSION)
+                    .build()
+                    .into_bytes(),
+            }),
+            QrFormat::Png => {
+                let image = code
+                    .render::<Luma<u8>>()
+                    .min_dimensions(MIN_DIMENSION, MIN_DIMENSION)
+                    .build();
+                let mut body = Cursor::new(Vec::new());
+                DynamicImage::ImageLuma8(image)
+                    .write_to(&mut body, ImageOutputFormat::Png)
+                    .map_err(QrCodeError::Render)?;
+
+                Ok(Self {
+                    content_type: ContentType::PNG,
+                    body: body.into_inner(),
+                })
+            }
+        }
+    }
+}
+
+impl QrImage {
+    fn etag(&self) -> String {
+        let digest = Sha256::digest(&self.body);
+        let mut etag = String::from("\"");
+        for byte in &digest[..16] {
+            write!(etag, "{byte:02x}").unwrap();
+        }
+        etag.push('"');
+
+        etag
+    }
+}
+
+fn matches_etag(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').any(|candidate| {
+        let candidate = candidate.trim();
+        candidate == "*" || candidate.trim_start_matches("W/") == etag
+    })
+}
+
+impl<'r> Responder<'r, 'static> for QrImage {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let etag = self.etag();
+        let not_modified = request
+            .headers()
+            .get("If-None-Match")
+            .any(|if_none_match| matches_etag(if_none_match, &etag));
+
+        let mut response = if not_modified {
+            Response::build().status(Status::NotModified).finalize()
+        } else {
+            (self.content_type, self.body).respond_to(request)?
+        };
+        response.set_header(Header::new(
+            "Cache-Control",
+            format!("public, max-age={CACHE_MAX_AGE_SECONDS}"),
+        ));
+        response.set_header(Header::new("ETag", etag));
+
+        Ok(response)
+    }
+}
@@ -0,0 +1,115 @@
+use rocket::{http::Status, response::Responder, Request, Response};
+use serde_json::json;
+use std::{fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{config::Config, models::ranking::RankingWeights, services::write_json_atomically};
+
+#[derive(Error, Debug)]
+pub enum RankingConfigError {
+    #[error("Invalid ranking weights: {0}")]
+    InvalidWeights(&'static str),
+    #[error("Unable to read or write ranking config file: {0}")]
+    ConfigFile(std::io::Error),
+    #[error("Ranking config file is not valid: {0}")]
+    InvalidConfigFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for RankingConfigError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidWeights(_) => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+///
+/// Search ranking weights, editable by admins without a restart. Changes are saved to
+/// `RANKING_CONFIG_FILE` when that is set, and the file can be reloaded after editing it by hand
+pub struct RankingConfig {
+    weights: RwLock<RankingWeights>,
+    config_file: Option<PathBuf>,
+}
+
+fn validate(weights: &RankingWeights) -> Result<(), RankingConfigError> {
+    if !(0.0..=1.0).contains(&weights.similarity_threshold) {
+        return Err(RankingConfigError::InvalidWeights(
+            "similarity threshold must be between 0 and 1",
+        ));
+    }
+    if weights.similarity_weight < 0.0 || weights.recency_weight < 0.0 {
+        return Err(RankingConfigError::InvalidWeights(
+            "weights cannot be negative",
+        ));
+    }
+    if weights.recency_half_life_days <= 0.0 {
+        return Err(RankingConfigError::InvalidWeights(
+            "recency half life must be more than zero days",
+        ));
+    }
+
+    Ok(())
+}
+
+fn read_weights(path: &PathBuf) -> Result<RankingWeights, RankingConfigError> {
+    let contents = fs::read_to_string(path).map_err(RankingConfigError::ConfigFile)?;
+    let weights = serde_json::from_str(&contents)
+        .map_err(|e| RankingConfigError::InvalidConfigFile(e.to_string()))?;
+    validate(&weights).map_err(|e| RankingConfigError::InvalidConfigFile(e.to_string()))?;
+
+    Ok(weights)
+}
+
+impl RankingConfig {
+    pub fn from_config(config: &Config) -> Result<Self, RankingConfigError> {
+        let config_file = config.ranking_config_file.as_ref().map(PathBuf::from);
+        let weights = match config_file {
+            Some(ref path) if path.exists() => read_weights(path)?,
+            _ => RankingWeights::default(),
+        };
+
+        Ok(Self {
+            weights: RwLock::new(weights),
+            config_file,
+        })
+    }
+
+    pub fn weights(&self) -> RankingWeights {
+        *self.weights.read().unwrap()
+    }
+
+    ///
+    /// Replaces the weights, saving them first so a failed write leaves the current weights in
+    /// place
+    pub fn update(&self, weights: RankingWeights) -> Result<(), RankingConfigError> {
+        validate(&weights)?;
+
+        if let Some(ref path) = self.config_file {
+            write_json_atomically(path, &weights).map_err(RankingConfigError::ConfigFile)?;
+        }
+
+        *self.weights.write().unwrap() = weights;
+        Ok(())
+    }
+
+    ///
+    /// Re-reads the weights from the config file, keeping the current weights if it is missing or
+    /// invalid
+    pub fn reload(&self) -> Result<RankingWeights, RankingConfigError> {
+        let weights = match self.config_file {
+            Some(ref path) if path.exists() => read_weights(path)?,
+            _ => return Ok(self.weights()),
+        };
+
+        *self.weights.write().unwrap() = weights;
+        Ok(weights)
+    }
+}
@@ -0,0 +1,132 @@
+use rocket::{http::Status, response::Responder, Request, Response};
+use std::{collections::BTreeSet, fs, path::PathBuf, sync::RwLock};
+use thiserror::Error;
+
+use crate::{config::Config, services::write_json_atomically};
+
+/// Reserved even when the reserved usernames file doesn't list them
+const DEFAULT_RESERVED: &[&'static str] = &["admin", "support", "tekxchange"];
+
+#[derive(Error, Debug)]
+pub enum ReservedUsernamesError {
+    #[error("Unable to read or write reserved usernames file: {0}")]
+    UsernamesFile(std::io::Error),
+    #[error("Reserved usernames file is not valid: {0}")]
+    InvalidUsernamesFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for ReservedUsernamesError {
+    fn respond_to(self, _: &'r Request<'_>) -> rocket::response::Result<'static> {
+        println!("{self}");
+        Response::build().status(Status::InternalServerError).ok()
+    }
+}
+
+///
+/// Lowercases and strips separators, folding digits commonly swapped for letters, so `Supp0rt`,
+/// `s.u.p.p.o.r.t`, and `support` all compare equal
+fn skeleton(username: &str) -> String {
+    username
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .map(|c| match c {
+            '0' => 'o',
+            '1' => 'l',
+            '3' => 'e',
+            '4' => 'a',
+            '5' => 's',
+            '7' => 't',
+            'i' => 'l',
+            c => c,
+        })
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != *b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+///
+/// Usernames new accounts may not take: brand and system names, plus anything that looks like a
+/// staff member's username. Admin changes are saved to `RESERVED_USERNAMES_FILE` when that is set
+pub struct ReservedUsernames {
+    names: RwLock<BTreeSet<String>>,
+    usernames_file: Option<PathBuf>,
+}
+
+impl ReservedUsernames {
+    pub fn from_config(config: &Config) -> Result<Self, ReservedUsernamesError> {
+        let usernames_file = config.reserved_usernames_file.as_ref().map(PathBuf::from);
+        let mut names: BTreeSet<String> = match usernames_file {
+            Some(ref path) if path.exists() => {
+                let contents =
+                    fs::read_to_string(path).map_err(ReservedUsernamesError::UsernamesFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| ReservedUsernamesError::InvalidUsernamesFile(e.to_string()))?
+            }
+            _ => BTreeSet::new(),
+        };
+        names.extend(DEFAULT_RESERVED.iter().map(|name| name.to_string()));
+
+        Ok(Self {
+            names: RwLock::new(names),
+            usernames_file,
+        })
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.names.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn add(&self, username: &str) -> Result<(), ReservedUsernamesError> {
+        let mut names = self.names.write().unwrap();
+        names.insert(username.trim().to_lowercase());
+        self.save(&names)
+    }
+
+    pub fn remove(&self, username: &str) -> Result<(), ReservedUsernamesError> {
+        let mut names = self.names.write().unwrap();
+        names.remove(&username.trim().to_lowercase());
+        self.save(&names)
+    }
+
+    ///
+    /// Whether `username` matches a reserved name, or is within one edit of a staff username once
+    /// both are reduced to their skeletons
+    pub fn is_reserved(&self, username: &str, staff_usernames: &[String]) -> bool {
+        let candidate = skeleton(username);
+        let reserved = self
+            .names
+            .read()
+            .unwrap()
+            .iter()
+            .any(|name| skeleton(name) == candidate);
+
+        reserved
+            || staff_usernames
+                .iter()
+                .any(|staff| edit_distance(&skeleton(staff), &candidate) <= 1)
+    }
+
+    fn save(&self, names: &BTreeSet<String>) -> Result<(), ReservedUsernamesError> {
+        let path = match self.usernames_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        write_json_atomically(path, names).map_err(ReservedUsernamesError::UsernamesFile)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,271 @@
+use chrono::{NaiveDateTime, Utc};
+use entity::{product::Entity as ProductEntity, user};
+use rocket::{
+    http::Status,
+    response::Responder,
+    tokio::{
+        self,
+        time::{interval, Duration},
+    },
+    Request, Response,
+};
+use sea_orm::{entity::prelude::*, query::Condition, sea_query::Expr, DatabaseConnection, Value};
+use serde_json::json;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::PathBuf,
+    sync::{Arc, RwLock},
+};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    db::{establish_connection, DbError},
+    models::segment::{SegmentPredicate, SegmentReturn},
+    services::write_json_atomically,
+};
+
+#[derive(Error, Debug)]
+pub enum SegmentsError {
+    #[error("Segment names may only contain lowercase letters, digits, and dashes")]
+    InvalidName,
+    #[error(transparent)]
+    DbError(DbError),
+    #[error(transparent)]
+    OrmError(sea_orm::DbErr),
+    #[error("Unable to read or write segments file: {0}")]
+    SegmentsFile(std::io::Error),
+    #[error("Segments file is not valid: {0}")]
+    InvalidSegmentsFile(String),
+}
+
+impl<'r> Responder<'r, 'static> for SegmentsError {
+    fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
+        match self {
+            Self::InvalidName => {
+                Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
+                    .status(Status::BadRequest)
+                    .ok()
+            }
+            _ => {
+                println!("{self}");
+                Response::build().status(Status::InternalServerError).ok()
+            }
+        }
+    }
+}
+
+struct Materialized {
+    user_ids: HashSet<i64>,
+    materialized_at: NaiveDateTime,
+}
+
+///
+/// Named user segments used to target experiments. Definitions are saved to `SEGMENTS_FILE` when
+/// that is set, and membership is recomputed every `SEGMENT_REFRESH_MINUTES` (0 disables this) or
+/// on demand. A segment has no members until it has been materialized
+pub struct Segments {
+    definitions: RwLock<BTreeMap<String, SegmentPredicate>>,
+    members: RwLock<HashMap<String, Materialized>>,
+    segments_file: Option<PathBuf>,
+    refresh_every: Duration,
+}
+
+fn to_condition(predicate: &SegmentPredicate) -> Condition {
+    let user_id = format!("\"{}\".\"id\"", user::Entity.table_name());
+    let products = ProductEntity.table_name();
+    let listing_count =
+        format!("(SELECT COUNT(*) FROM \"{products}\" WHERE created_by = {user_id})");
+
+    match predicate {
+        SegmentPredicate::All(predicates) => predicates
+            .iter()
+            .fold(Condition::all(), |all, p| all.add(to_condition(p))),
+        SegmentPredicate::Any(predicates) => predicates
+            .iter()
+            .fold(Condition::any(), |any, p| any.add(to_condition(p))),
+        SegmentPredicate::Not(predicate) => to_condition(predicate).not(),
+        SegmentPredicate::SignedUpAfter(date) => {
+            Condition::all().add(user::Column::CreatedAt.gte(*date))
+        }
+        SegmentPredicate::SignedUpBefore(date) => {
+            Condition::all().add(user::Column::CreatedAt.lt(*date))
+        }
+        SegmentPredicate::MinListings(count) => Condition::all().add(Expr::cust_with_values(
+            &format!("{listing_count} >= $1"),
+            vec![Value::from(*count as i64)],
+        )),
+        SegmentPredicate::MaxListings(count) => Condition::all().add(Expr::cust_with_values(
+            &format!("{listing_count} <= $1"),
+            vec![Value::from(*count as i64)],
+        )),
+        SegmentPredicate::Role(role) => Condition::all().add(user::Column::Role.eq(*role as i16)),
+        SegmentPredicate::ListsIn(region) => Condition::all().add(Expr::cust_with_values(
+            &format!(
+                "EXISTS (SELECT 1 FROM \"{products}\" \
+                WHERE created_by = {user_id} AND lower(location_state) = lower($1))"
+            ),
+            vec![Value::from(region.as_str())],
+        )),
+    }
+}
+
+impl Segments {
+    pub fn from_config(config: &Config) -> Result<Self, SegmentsError> {
+        let segments_file = config.segments_file.as_ref().map(PathBuf::from);
+        let definitions = match segments_file {
+            Some(ref path) if path.exists() => {
+                let contents = fs::read_to_string(path).map_err(SegmentsError::SegmentsFile)?;
+                serde_json::from_str(&contents)
+                    .map_err(|e| SegmentsError::InvalidSegmentsFile(e.to_string()))?
+            }
+            _ => BTreeMap::new(),
+        };
+
+        Ok(Self {
+            definitions: RwLock::new(definitions),
+            members: RwLock::new(HashMap::new()),
+            segments_file,
+            refresh_every: Duration::from_secs(config.segment_refresh_minutes * 60),
+        })
+    }
+
+    pub fn all(&self) -> Vec<SegmentReturn> {
+        let members = self.members.read().unwrap();
+        self.definitions
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(name, predicate)| {
+                let materialized = members.get(name);
+                SegmentReturn {
+                    name: name.clone(),
+                    predicate: predicate.clone(),
+                    member_count: materialized.map(|m| m.user_ids.len()),
+                    materialized_at: materialized.map(|m| m.materialized_at),
+                }
+            })
+            .collect()
+    }
+
+    ///
+    /// Whether the user was in the segment when it was last materialized
+    pub fn contains(&self, name: &str, user_id: i64) -> bool {
+        self.members
+            .read()
+            .unwrap()
+            .get(name)
+            .is_some_and(|m| m.user_ids.contains(&user_id))
+    }
+
+    pub fn members(&self, name: &str) -> Option<Vec<i64>> {
+        let members = self.members.read().unwrap();
+        let mut user_ids: Vec<i64> = members.get(name)?.user_ids.iter().copied().collect();
+        user_ids.sort_unstable();
+
+        Some(user_ids)
+    }
+
+    ///
+    /// Creates or replaces a segment. Its previous membership is dropped until the next refresh
+    pub fn upsert(&self, name: &str, predicate: SegmentPredicate) -> Result<(), SegmentsError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            return Err(SegmentsError::InvalidName);
+        }
+
+        let mut definitions = self.definitions.write().unwrap();
+        definitions.insert(name.to_owned(), predicate);
+        self.save(&definitions)?;
+        self.members.write().unwrap().remove(name);
+
+        Ok(())
+    }
+
+    ///
+    /// Deletes a segment. Returns false if there is no segment with the name
+    pub fn remove(&self, name: &str) -> Result<bool, SegmentsError> {
+        let mut definitions = self.definitions.write().unwrap();
+        if definitions.remove(name).is_none() {
+            return Ok(false);
+        }
+
+        self.save(&definitions)?;
+        self.members.write().unwrap().remove(name);
+        Ok(true)
+    }
+
+    fn save(&self, definitions: &BTreeMap<String, SegmentPredicate>) -> Result<(), SegmentsError> {
+        let path = match self.segments_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        write_json_atomically(path, definitions).map_err(SegmentsError::SegmentsFile)?;
+
+        Ok(())
+    }
+
+    async fn materialize(
+        db_connection: &DatabaseConnection,
+        predicate: &SegmentPredicate,
+    ) -> Result<Materialized, SegmentsError> {
+        let user_ids = user::Entity::find()
+            .filter(to_condition(predicate))
+            .all(db_connection)
+            .await
+            .map_err(SegmentsError::OrmError)?
+            .into_iter()
+            .map(|user| user.id)
+            .collect();
+
+        Ok(Materialized {
+            user_ids,
+            materialized_at: Utc::now().naive_utc(),
+        })
+    }
+
+    ///
+    /// Recomputes the members of every segment. Returns the number of segments refreshed
+    pub async fn refresh(&self, config: &Config) -> Result<usize, SegmentsError> {
+        let db_connection = establish_connection(config)
+            .await
+            .map_err(SegmentsError::DbError)?;
+        let definitions = self.definitions.read().unwrap().clone();
+
+        for (name, predicate) in &definitions {
+            let materialized = Self::materialize(&db_connection, predicate).await?;
+            // Skip segments that were deleted while this one was being computed
+            if self.definitions.read().unwrap().contains_key(name) {
+                self.members
+                    .write()
+                    .unwrap()
+                    .insert(name.clone(), materialized);
+            }
+        }
+
+        Ok(definitions.len())
+    }
+
+    ///
+    /// Periodically refreshes segment membership. A failed refresh keeps the previous members
+    pub fn spawn_refresh_job(self: Arc<Self>, config: Config) {
+        if self.refresh_every.is_zero() {
+            return;
+        }
+
+        tokio::spawn(async move {
+            let mut ticker = interval(self.refresh_every);
+            loop {
+                ticker.tick().await;
+                match self.refresh(&config).await {
+                    Ok(count) => println!("Refreshed user segments ({count} segments)"),
+                    Err(e) => println!("Unable to refresh user segments: {e:?}"),
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::{Duration, Utc};
+use entity::product::{Column as ProductColumn, Entity as ProductEntity};
+use sea_orm::{prelude::*, query::Condition, DatabaseConnection};
+use thiserror::Error;
+
+use crate::{config::Config, models::user::User};
+
+#[derive(Error, Debug)]
+pub enum SpamGuardError {
+    #[error(transparent)]
+    OrmError(sea_orm::DbErr),
+    #[error("New accounts may only create {0} listings per day")]
+    ListingLimitReached(u64),
+}
+
+///
+/// Restrictions applied to accounts younger than `new_account_hours`, configured through
+/// `SPAM_NEW_ACCOUNT_HOURS` and `SPAM_MAX_LISTINGS_PER_DAY`
+pub struct SpamGuard {
+    new_account_hours: i64,
+    max_listings_per_day: u64,
+}
+
+impl SpamGuard {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            new_account_hours: config.spam_new_account_hours,
+            max_listings_per_day: config.spam_max_listings_per_day,
+        }
+    }
+
+    fn is_new_account(&self, user: &User) -> bool {
+        Utc::now().naive_utc() - user.created_at < Duration::hours(self.new_account_hours)
+    }
+
+    pub async fn check_new_listing(
+        &self,
+        db_connection: &DatabaseConnection,
+        user: &User,
+    ) -> Result<(), SpamGuardError> {
+        if !self.is_new_account(user) {
+            return Ok(());
+        }
+
+        let since = Utc::now().naive_utc() - Duration::days(1);
+        let listings_today = ProductEntity::find()
+            .filter(
+                Condition::all()
+                    .add(ProductColumn::CreatedBy.eq(user.id))
+                    .add(ProductColumn::CreatedAt.gte(since)),
+            )
+            .count(db_connection)
+            .await
+            .map_err(SpamGuardError::OrmError)?;
+
+        if listings_today >= self.max_listings_per_day {
+            return Err(SpamGuardError::ListingLimitReached(
+                self.max_listings_per_day,
+            ));
+        }
+
+        Ok(())
+    }
+}
@@ -1,14 +1,16 @@
 use crate::{
-    db::establish_connection,
+    config::Config,
+    db::{establish_connection, DbError},
     models::{
         role::Role,
+        scope::ALL_SCOPES,
         user::{UserLogin, UserRegister},
     },
+    services::{CaptchaError, JwtKeyring, KeywordPolicyError},
 };
-use argon2::{self, Config};
+use argon2::{self, Variant, Version};
 use chrono::offset::Utc;
 use entity::user::{Entity as UserEntity, Model as UserModel};
-use hmac::{Hmac, Mac};
 use jwt::{AlgorithmType, Header as JwtHeader};
 use rand::{distributions::Alphanumeric, Rng};
 use rocket::{
@@ -17,16 +19,24 @@ use rocket::{
     response::Responder,
     Request, Response,
 };
-use sea_orm::{prelude::*, query::Condition, ActiveValue, DatabaseConnection, Set};
+use sea_orm::{
+    prelude::*, query::Condition, sea_query::Expr, ActiveValue, DatabaseConnection, Set,
+    TransactionTrait,
+};
 use serde_json::json;
-use sha2::Sha512;
-use std::{collections::BTreeMap, env};
+use std::{collections::BTreeMap, sync::Arc};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum UserServiceError {
     #[error("This username and/or email already exists")]
     DuplicateUserError,
+    #[error("Disposable email addresses are not allowed")]
+    DisposableEmail,
+    #[error("That username is reserved")]
+    ReservedUsername,
+    #[error("An account cannot be merged into itself")]
+    SelfMerge,
     #[error(transparent)]
     DbError(crate::db::DbError),
     #[error(transparent)]
@@ -37,6 +47,10 @@ pub enum UserServiceError {
     InvalidToken,
     #[error("Incorrect password provided")]
     InvalidPassword,
+    #[error(transparent)]
+    CaptchaError(CaptchaError),
+    #[error(transparent)]
+    PolicyError(KeywordPolicyError),
     #[error("An unknown error occurred")]
     Unknown,
 }
@@ -44,7 +58,12 @@ pub enum UserServiceError {
 impl<'r> Responder<'r, 'static> for UserServiceError {
     fn respond_to(self, request: &'r Request<'_>) -> rocket::response::Result<'static> {
         match self {
-            Self::DuplicateUserError | Self::InvalidPassword | Self::InvalidToken => {
+            Self::DuplicateUserError
+            | Self::DisposableEmail
+            | Self::ReservedUsername
+            | Self::SelfMerge
+            | Self::InvalidPassword
+            | Self::InvalidToken => {
                 Response::build_from(json!({ "error": format!("{self}") }).respond_to(request)?)
                     .status(Status::BadRequest)
                     .ok()
@@ -54,38 +73,109 @@ impl<'r> Responder<'r, 'static> for UserServiceError {
                     .status(Status::NotFound)
                     .ok()
             }
+            Self::CaptchaError(e) => e.respond_to(request),
+            Self::PolicyError(e) => e.respond_to(request),
             _ => Response::build().status(Status::InternalServerError).ok(),
         }
     }
 }
 
+///
+/// Argon2id parameters used for new password hashes, configured through `ARGON2_MEM_COST` (in
+/// KiB), `ARGON2_TIME_COST`, and `ARGON2_LANES`
+#[derive(Clone, Copy, Debug)]
+pub struct PasswordHashConfig {
+    mem_cost: u32,
+    time_cost: u32,
+    lanes: u32,
+}
+
+impl PasswordHashConfig {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            mem_cost: config.argon2_mem_cost,
+            time_cost: config.argon2_time_cost,
+            lanes: config.argon2_lanes,
+        }
+    }
+
+    fn argon2_config(&self) -> argon2::Config<'static> {
+        argon2::Config {
+            variant: Variant::Argon2id,
+            version: Version::Version13,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+            lanes: self.lanes,
+            ..Default::default()
+        }
+    }
+
+    fn hash(&self, password: &str) -> Result<String, UserServiceError> {
+        let salt: String = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(20)
+            .map(char::from)
+            .collect();
+
+        argon2::hash_encoded(password.as_bytes(), salt.as_bytes(), &self.argon2_config())
+            .map_err(|_| UserServiceError::Unknown)
+    }
+
+    ///
+    /// Whether `encoded` was produced with a different variant or different parameters than the
+    /// ones currently configured
+    fn needs_rehash(&self, encoded: &str) -> bool {
+        let config = self.argon2_config();
+        let expected_prefix = format!(
+            "${}$v={}$m={},t={},p={}$",
+            config.variant, config.version, config.mem_cost, config.time_cost, config.lanes
+        );
+
+        !encoded.starts_with(&expected_prefix)
+    }
+}
+
 pub struct UserService {
     db_connection: DatabaseConnection,
-    jwt_key: Hmac<Sha512>,
+    jwt_keyring: Arc<JwtKeyring>,
     signing_alg: AlgorithmType,
+    password_hash_config: PasswordHashConfig,
 }
 
 #[rocket::async_trait]
 impl<'r> FromRequest<'r> for UserService {
     type Error = UserServiceError;
 
-    async fn from_request(_: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
-        let secret = env::var("SECRET").unwrap();
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let config = match req.rocket().state::<Config>() {
+            Some(config) => config,
+            None => {
+                return request::Outcome::Failure((
+                    rocket::http::Status::InternalServerError,
+                    UserServiceError::DbError(DbError::ConfigError),
+                ))
+            }
+        };
 
-        let key = Hmac::new_from_slice(secret.as_bytes()).map_err(|_| UserServiceError::Unknown);
-        if let Err(e) = key {
-            return request::Outcome::Failure((rocket::http::Status::InternalServerError, e));
-        }
-        let key: Hmac<Sha512> = key.unwrap();
+        let jwt_keyring = match req.rocket().state::<Arc<JwtKeyring>>() {
+            Some(jwt_keyring) => jwt_keyring.clone(),
+            None => {
+                return request::Outcome::Failure((
+                    rocket::http::Status::InternalServerError,
+                    UserServiceError::Unknown,
+                ))
+            }
+        };
 
-        match establish_connection()
+        match establish_connection(config)
             .await
             .map_err(|e| UserServiceError::DbError(e))
         {
             Ok(conn) => request::Outcome::Success(Self {
                 db_connection: conn,
-                jwt_key: key,
+                jwt_keyring,
                 signing_alg: AlgorithmType::Hs512,
+                password_hash_config: PasswordHashConfig::from_config(config),
             }),
             Err(e) => request::Outcome::Failure((rocket::http::Status::InternalServerError, e)),
         }
@@ -93,14 +183,16 @@ impl<'r> FromRequest<'r> for UserService {
 }
 
 impl UserService {
-    pub fn new(db_connection: DatabaseConnection) -> Self {
-        let secret = env::var("SECRET").unwrap();
-
-        let key = Hmac::new_from_slice(secret.as_bytes()).unwrap();
+    pub fn new(
+        db_connection: DatabaseConnection,
+        config: &Config,
+        jwt_keyring: Arc<JwtKeyring>,
+    ) -> Self {
         Self {
             db_connection,
-            jwt_key: key,
+            jwt_keyring,
             signing_alg: AlgorithmType::Hs512,
+            password_hash_config: PasswordHashConfig::from_config(config),
         }
     }
 
@@ -124,16 +216,7 @@ impl UserService {
             return Err(UserServiceError::DuplicateUserError);
         }
 
-        let salt: String = rand::thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(20)
-            .map(char::from)
-            .collect();
-        let config = Config::default();
-
-        register.password =
-            argon2::hash_encoded(register.password.as_bytes(), salt.as_bytes(), &config)
-                .map_err(|_| UserServiceError::Unknown)?;
+        register.password = self.password_hash_config.hash(&register.password)?;
 
         let user_active_model = user::ActiveModel {
             email: ActiveValue::Set(register.email.to_owned()),
@@ -149,12 +232,34 @@ impl UserService {
         Ok(created_user.id)
     }
 
-    pub async fn validate_token(&mut self, token: &str) -> Result<UserModel, UserServiceError> {
-        use jwt::VerifyWithKey;
+    ///
+    /// Returns the token's user along with the scopes it grants. A token without a `scope` claim
+    /// grants no scopes
+    pub async fn validate_token(
+        &mut self,
+        token: &str,
+    ) -> Result<(UserModel, Vec<String>), UserServiceError> {
+        use jwt::{Token, VerifyWithKey};
+
+        let unverified: Token<JwtHeader, BTreeMap<String, String>, _> =
+            Token::parse_unverified(token).map_err(|_| UserServiceError::InvalidToken)?;
+        let key = self
+            .jwt_keyring
+            .verifying_key(unverified.header().key_id.as_deref())
+            .ok_or(UserServiceError::InvalidToken)?;
+        let verified: Token<JwtHeader, BTreeMap<String, String>, _> = unverified
+            .verify_with_key(&key)
+            .map_err(|_| UserServiceError::InvalidToken)?;
+        let claims = verified.claims();
 
-        let claims: BTreeMap<String, String> = token
-            .verify_with_key(&self.jwt_key)
+        let exp = claims
+            .get("exp")
+            .ok_or(UserServiceError::InvalidToken)?
+            .parse::<i64>()
             .map_err(|_| UserServiceError::InvalidToken)?;
+        if exp < Utc::now().timestamp() {
+            return Err(UserServiceError::InvalidToken);
+        }
 
         let user_id = claims.get("sub");
         if let None = user_id {
@@ -165,10 +270,17 @@ impl UserService {
             .parse::<i64>()
             .map_err(|_| UserServiceError::Unknown)?;
 
-        Ok(self
+        let scopes = match claims.get("scope") {
+            Some(scope) => scope.split_whitespace().map(str::to_owned).collect(),
+            None => Vec::new(),
+        };
+
+        let user = self
             .get_user_by_id(&user_id)
             .await?
-            .ok_or_else(|| UserServiceError::InvalidToken)?)
+            .ok_or_else(|| UserServiceError::InvalidToken)?;
+
+        Ok((user, scopes))
     }
 
     async fn get_by_email(&mut self, email: &str) -> Result<Option<UserModel>, UserServiceError> {
@@ -206,25 +318,36 @@ impl UserService {
             .map_err(|e| UserServiceError::OrmError(e))?)
     }
 
-    fn generate_jwt(&mut self, user: &UserModel) -> Result<String, UserServiceError> {
+    fn generate_jwt(
+        &mut self,
+        user: &UserModel,
+        scopes: &[&str],
+    ) -> Result<String, UserServiceError> {
         use jwt::{SignWithKey, Token};
 
         let user_id = user.id.to_string();
         let role_id = user.role.to_string();
-        let now = Utc::now().timestamp().to_string();
+        let now = Utc::now().timestamp();
+        let issued_at = now.to_string();
+        let expires_at = (now + self.jwt_keyring.token_ttl_seconds()).to_string();
+        let scope = scopes.join(" ");
 
         let mut claims: BTreeMap<&str, &str> = BTreeMap::new();
         claims.insert("sub", &user_id);
         claims.insert("role", &role_id);
-        claims.insert("iat", &now);
+        claims.insert("iat", &issued_at);
+        claims.insert("exp", &expires_at);
+        claims.insert("scope", &scope);
 
+        let (key_id, key) = self.jwt_keyring.signing_key();
         let jwt_headers = JwtHeader {
             algorithm: self.signing_alg,
+            key_id: Some(key_id),
             ..Default::default()
         };
 
         let token = Token::new(jwt_headers, claims)
-            .sign_with_key(&self.jwt_key)
+            .sign_with_key(&key)
             .map_err(|e| {
                 println!("{e:?}");
                 UserServiceError::Unknown
@@ -245,6 +368,20 @@ impl UserService {
         return Ok(found_count > 0);
     }
 
+    ///
+    /// Usernames of moderators and admins
+    pub async fn staff_usernames(&mut self) -> Result<Vec<String>, UserServiceError> {
+        use entity::user;
+
+        let staff = UserEntity::find()
+            .filter(user::Column::Role.ne(Role::User as i16))
+            .all(&self.db_connection)
+            .await
+            .map_err(UserServiceError::OrmError)?;
+
+        Ok(staff.into_iter().map(|user| user.username).collect())
+    }
+
     pub async fn email_exists(&mut self, email: &str) -> Result<bool, UserServiceError> {
         use entity::user;
 
@@ -257,7 +394,12 @@ impl UserService {
         return Ok(found_count > 0);
     }
 
-    pub async fn login(&mut self, login: UserLogin) -> Result<String, UserServiceError> {
+    ///
+    /// Looks up the account a login is for, by email when one is given and otherwise by username
+    pub async fn find_login_user(
+        &mut self,
+        login: &UserLogin,
+    ) -> Result<UserModel, UserServiceError> {
         let mut user: Option<UserModel> = None;
 
         if let Some(ref email) = login.email {
@@ -266,26 +408,103 @@ impl UserService {
             user = self.get_by_username(username).await?;
         }
 
-        if let None = user {
-            return Err(UserServiceError::UserNotFound);
-        }
-
-        let user: UserModel = user.unwrap();
+        user.ok_or(UserServiceError::UserNotFound)
+    }
 
-        match argon2::verify_encoded(&user.password, login.password.as_bytes()) {
+    ///
+    /// Checks `password` against the account found by `find_login_user` and issues a token
+    pub async fn login(
+        &mut self,
+        user: &UserModel,
+        password: &str,
+    ) -> Result<String, UserServiceError> {
+        match argon2::verify_encoded(&user.password, password.as_bytes()) {
             Ok(success) => {
                 if !success {
                     return Err(UserServiceError::InvalidPassword);
                 }
-                return Ok(self.generate_jwt(&user)?);
+                if self.password_hash_config.needs_rehash(&user.password) {
+                    if let Err(e) = self.rehash_password(user, password).await {
+                        println!("Unable to upgrade password hash for user {}: {e:?}", user.id);
+                    }
+                }
+                return Ok(self.generate_jwt(user, ALL_SCOPES)?);
             }
             Err(e) => {
                 println!("{e:?}");
-                todo!();
+                return Err(UserServiceError::Unknown);
             }
         }
     }
 
+    ///
+    /// Re-hashes a verified password with the current Argon2id parameters so accounts created
+    /// with legacy hashes migrate on their next login
+    async fn rehash_password(
+        &mut self,
+        user: &UserModel,
+        password: &str,
+    ) -> Result<(), UserServiceError> {
+        let mut active_user: entity::user::ActiveModel = user.clone().into();
+        active_user.password = Set(self.password_hash_config.hash(password)?);
+        active_user
+            .update(&self.db_connection)
+            .await
+            .map_err(UserServiceError::OrmError)?;
+
+        Ok(())
+    }
+
+    ///
+    /// Moves every listing owned by `duplicate_id` to `primary_id` in one transaction, returning
+    /// the ids of the moved listings so the merge can be undone. The duplicate account is kept
+    pub async fn merge_users(
+        &mut self,
+        primary_id: i64,
+        duplicate_id: i64,
+    ) -> Result<Vec<i64>, UserServiceError> {
+        use entity::product;
+
+        if primary_id == duplicate_id {
+            return Err(UserServiceError::SelfMerge);
+        }
+        let txn = self
+            .db_connection
+            .begin()
+            .await
+            .map_err(UserServiceError::OrmError)?;
+
+        for id in [primary_id, duplicate_id] {
+            UserEntity::find_by_id(id)
+                .one(&txn)
+                .await
+                .map_err(UserServiceError::OrmError)?
+                .ok_or(UserServiceError::UserNotFound)?;
+        }
+
+        let moved: Vec<i64> = product::Entity::find()
+            .filter(product::Column::CreatedBy.eq(duplicate_id))
+            .all(&txn)
+            .await
+            .map_err(UserServiceError::OrmError)?
+            .into_iter()
+            .map(|product| product.id)
+            .collect();
+
+        product::Entity::update_many()
+            .col_expr(product::Column::CreatedBy, Expr::value(primary_id))
+            .filter(product::Column::Id.is_in(moved.clone()))
+            .exec(&txn)
+            .await
+            .map_err(UserServiceError::OrmError)?;
+
+        txn.commit()
+            .await
+            .map_err(UserServiceError::OrmError)?;
+
+        Ok(moved)
+    }
+
     pub async fn update_role_for_user(
         &mut self,
         user_id: i64,